@@ -8,8 +8,14 @@ struct Config {
     application: Application,
     board: Board,
     audio: Audio,
+    listen: Listen,
+    wake_word: WakeWord,
+    voice_prompt: VoicePrompt,
     gui: Gui,
     network: Network,
+    mqtt: Mqtt,
+    outbound: Outbound,
+    device_info: DeviceInfo,
     hello_message: HelloMessage,
     features: Features,
     mcp: serde_json::Value,
@@ -36,6 +42,34 @@ struct Audio {
     playback_sample_rate: u32,
     playback_channels: u32,
     playback_period_size: usize,
+    pcm_sample_rate: u32,
+    pcm_channels: u32,
+    pcm_endian: String,
+    aec_filter_length_ms: u32,
+    barge_in_threshold_db: i32,
+    barge_in_min_speech_ms: u32,
+    vad_hangover_ms: u32,
+}
+
+#[derive(Deserialize)]
+struct Listen {
+    mode: String,
+    ptt_input_device: String,
+    ptt_key_code: u16,
+}
+
+#[derive(Deserialize)]
+struct WakeWord {
+    word: String,
+    templates: Vec<String>,
+    threshold: f32,
+}
+
+#[derive(Deserialize)]
+struct VoicePrompt {
+    dir: String,
+    activation_code_interval_secs: u32,
+    tone_fallback: bool,
 }
 
 #[derive(Deserialize)]
@@ -52,10 +86,43 @@ struct Network {
     ws_url: String,
     ota_url: String,
     ws_token: String,
+    activation_identity_file: String,
+    protocol_version: u8,
+    ping_interval_secs: u32,
+    pong_timeout_secs: u32,
+    read_timeout_secs: u32,
+    reconnect_min_secs: u32,
+    reconnect_max_secs: u32,
+    reconnect_stable_secs: u32,
+    transport: String,
     device_id: String,
     client_id: String,
 }
 
+#[derive(Deserialize)]
+struct Mqtt {
+    endpoint: String,
+    client_id: String,
+    username: String,
+    password: String,
+    publish_topic: String,
+    subscribe_topic: String,
+}
+
+#[derive(Deserialize)]
+struct Outbound {
+    proxy: String,
+    not_yet_valid_window_secs: u64,
+    ca_file: String,
+    client_cert_file: String,
+    client_key_file: String,
+}
+
+#[derive(Deserialize)]
+struct DeviceInfo {
+    exclude: Vec<String>,
+}
+
 #[derive(Deserialize)]
 struct HelloMessage {
     format: String,
@@ -67,11 +134,31 @@ struct HelloMessage {
 #[derive(Deserialize)]
 struct Features {
     enable_tts_display: bool,
+    enable_emotion_display: bool,
+    enable_aec: bool,
+    enable_barge_in: bool,
+    enable_wake_word: bool,
+    enable_ota_upgrade: bool,
+    ota_max_boot_attempts: u32,
+    enable_system_time_sync: bool,
 }
 
 // 在编译时读取 config.toml 并设置环境变量
 fn main() {
     println!("cargo:rerun-if-changed=config.toml");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // 编译时间，作为无 RTC 设备校准时钟前的时间下限；可用 SOURCE_DATE_EPOCH 指定以便复现构建
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", build_timestamp);
 
     let config_path = Path::new("config.toml");
     if !config_path.exists() {
@@ -112,6 +199,64 @@ fn main() {
         "cargo:rustc-env=AUDIO_PLAYBACK_PERIOD_SIZE={}",
         config.audio.playback_period_size
     );
+    println!(
+        "cargo:rustc-env=AUDIO_PCM_SAMPLE_RATE={}",
+        config.audio.pcm_sample_rate
+    );
+    println!(
+        "cargo:rustc-env=AUDIO_PCM_CHANNELS={}",
+        config.audio.pcm_channels
+    );
+    println!(
+        "cargo:rustc-env=AUDIO_PCM_ENDIAN={}",
+        config.audio.pcm_endian
+    );
+    println!(
+        "cargo:rustc-env=AUDIO_AEC_FILTER_LENGTH_MS={}",
+        config.audio.aec_filter_length_ms
+    );
+    println!(
+        "cargo:rustc-env=AUDIO_BARGE_IN_THRESHOLD_DB={}",
+        config.audio.barge_in_threshold_db
+    );
+    println!(
+        "cargo:rustc-env=AUDIO_BARGE_IN_MIN_SPEECH_MS={}",
+        config.audio.barge_in_min_speech_ms
+    );
+    println!(
+        "cargo:rustc-env=AUDIO_VAD_HANGOVER_MS={}",
+        config.audio.vad_hangover_ms
+    );
+
+    // 拾音模式配置
+    println!("cargo:rustc-env=LISTEN_MODE={}", config.listen.mode);
+    println!(
+        "cargo:rustc-env=PTT_INPUT_DEVICE={}",
+        config.listen.ptt_input_device
+    );
+    println!("cargo:rustc-env=PTT_KEY_CODE={}", config.listen.ptt_key_code);
+
+    // 唤醒词配置
+    println!("cargo:rustc-env=WAKE_WORD={}", config.wake_word.word);
+    let templates_json =
+        serde_json::to_string(&config.wake_word.templates).expect("Failed to serialize wake word templates");
+    println!("cargo:rustc-env=WAKE_WORD_TEMPLATES_JSON={}", templates_json);
+    println!(
+        "cargo:rustc-env=WAKE_WORD_THRESHOLD={}",
+        config.wake_word.threshold
+    );
+
+    // 本地语音提示配置
+    println!("cargo:rustc-env=VOICE_PROMPT_DIR={}", config.voice_prompt.dir);
+    generate_tone_prompts(&Path::new(&env::var("OUT_DIR").unwrap()).join("tone_prompt"));
+    println!(
+        "cargo:rustc-env=ACTIVATION_CODE_PROMPT_INTERVAL_SECS={}",
+        config.voice_prompt.activation_code_interval_secs
+    );
+    println!(
+        "cargo:rustc-env=VOICE_PROMPT_TONE_FALLBACK={}",
+        config.voice_prompt.tone_fallback
+    );
 
     // GUI 配置
     println!("cargo:rustc-env=GUI_LOCAL_PORT={}", config.gui.local_port);
@@ -124,9 +269,65 @@ fn main() {
     println!("cargo:rustc-env=WS_URL={}", config.network.ws_url);
     println!("cargo:rustc-env=OTA_URL={}", config.network.ota_url);
     println!("cargo:rustc-env=WS_TOKEN={}", config.network.ws_token);
+    println!(
+        "cargo:rustc-env=ACTIVATION_IDENTITY_FILE={}",
+        config.network.activation_identity_file
+    );
+    println!(
+        "cargo:rustc-env=WS_PROTOCOL_VERSION={}",
+        config.network.protocol_version
+    );
+    println!(
+        "cargo:rustc-env=WS_PING_INTERVAL_SECS={}",
+        config.network.ping_interval_secs
+    );
+    println!(
+        "cargo:rustc-env=WS_PONG_TIMEOUT_SECS={}",
+        config.network.pong_timeout_secs
+    );
+    println!(
+        "cargo:rustc-env=WS_READ_TIMEOUT_SECS={}",
+        config.network.read_timeout_secs
+    );
+    println!(
+        "cargo:rustc-env=RECONNECT_MIN_SECS={}",
+        config.network.reconnect_min_secs
+    );
+    println!(
+        "cargo:rustc-env=RECONNECT_MAX_SECS={}",
+        config.network.reconnect_max_secs
+    );
+    println!(
+        "cargo:rustc-env=RECONNECT_STABLE_SECS={}",
+        config.network.reconnect_stable_secs
+    );
+    println!("cargo:rustc-env=NETWORK_TRANSPORT={}", config.network.transport);
     println!("cargo:rustc-env=DEVICE_ID={}", config.network.device_id);
     println!("cargo:rustc-env=CLIENT_ID={}", config.network.client_id);
 
+    // MQTT 配置
+    println!("cargo:rustc-env=MQTT_ENDPOINT={}", config.mqtt.endpoint);
+    println!("cargo:rustc-env=MQTT_CLIENT_ID={}", config.mqtt.client_id);
+    println!("cargo:rustc-env=MQTT_USERNAME={}", config.mqtt.username);
+    println!("cargo:rustc-env=MQTT_PASSWORD={}", config.mqtt.password);
+    println!("cargo:rustc-env=MQTT_PUBLISH_TOPIC={}", config.mqtt.publish_topic);
+    println!("cargo:rustc-env=MQTT_SUBSCRIBE_TOPIC={}", config.mqtt.subscribe_topic);
+
+    // 设备信息上报配置
+    let exclude_json = serde_json::to_string(&config.device_info.exclude)
+        .expect("Failed to serialize device_info exclude");
+    println!("cargo:rustc-env=DEVICE_INFO_EXCLUDE_JSON={}", exclude_json);
+
+    // 代理与自定义证书配置
+    println!("cargo:rustc-env=OUTBOUND_PROXY={}", config.outbound.proxy);
+    println!(
+        "cargo:rustc-env=OUTBOUND_NOT_YET_VALID_WINDOW_SECS={}",
+        config.outbound.not_yet_valid_window_secs
+    );
+    println!("cargo:rustc-env=OUTBOUND_CA_FILE={}", config.outbound.ca_file);
+    println!("cargo:rustc-env=OUTBOUND_CLIENT_CERT_FILE={}", config.outbound.client_cert_file);
+    println!("cargo:rustc-env=OUTBOUND_CLIENT_KEY_FILE={}", config.outbound.client_key_file);
+
     // Hello 消息配置
     println!(
         "cargo:rustc-env=HELLO_FORMAT={}",
@@ -150,6 +351,38 @@ fn main() {
         "cargo:rustc-env=ENABLE_TTS_DISPLAY={}",
         config.features.enable_tts_display
     );
+    println!(
+        "cargo:rustc-env=ENABLE_EMOTION_DISPLAY={}",
+        config.features.enable_emotion_display
+    );
+    println!(
+        "cargo:rustc-env=ENABLE_AEC={}",
+        config.features.enable_aec
+    );
+    println!(
+        "cargo:rustc-env=ENABLE_BARGE_IN={}",
+        config.features.enable_barge_in
+    );
+    println!(
+        "cargo:rustc-env=ENABLE_WAKE_WORD={}",
+        config.features.enable_wake_word
+    );
+    println!(
+        "cargo:rustc-env=ENABLE_OTA_UPGRADE={}",
+        config.features.enable_ota_upgrade
+    );
+    // 为 0 时新版本第一次启动就会回滚
+    if config.features.ota_max_boot_attempts == 0 {
+        panic!("config.toml: features.ota_max_boot_attempts must be at least 1");
+    }
+    println!(
+        "cargo:rustc-env=OTA_MAX_BOOT_ATTEMPTS={}",
+        config.features.ota_max_boot_attempts
+    );
+    println!(
+        "cargo:rustc-env=ENABLE_SYSTEM_TIME_SYNC={}",
+        config.features.enable_system_time_sync
+    );
 
     // MCP配置
     let mcp_json = serde_json::to_string(&config.mcp).expect("Failed to serialize mcp config");
@@ -186,6 +419,78 @@ fn main() {
     }
 }
 
+// 内置提示音的采样率（16kHz 单声道 16bit）
+const PROMPT_SAMPLE_RATE: u32 = 16000;
+
+/// 生成内置的后备提示音（不是语音），开启 tone_fallback 且 voice_prompt 目录缺少录音时使用
+/// 引导语为三个上升的音符，数字 0~9 为电话按键的 DTMF 双音
+fn generate_tone_prompts(dir: &Path) {
+    fs::create_dir_all(dir).expect("Failed to create tone prompt dir");
+
+    let mut lead_in = Vec::new();
+    for freq in [660.0, 880.0, 1320.0] {
+        lead_in.extend(tone(&[freq], 150));
+    }
+    lead_in.extend(silence(300));
+    write_wav(&dir.join("activation_code.wav"), &lead_in);
+
+    // DTMF 键盘的行频率和列频率
+    const ROWS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+    const COLS: [f32; 3] = [1209.0, 1336.0, 1477.0];
+    for digit in 0..10usize {
+        let (row, col) = match digit {
+            0 => (3, 1),
+            d => ((d - 1) / 3, (d - 1) % 3),
+        };
+        let mut samples = tone(&[ROWS[row], COLS[col]], 250);
+        samples.extend(silence(150));
+        write_wav(&dir.join(format!("{}.wav", digit)), &samples);
+    }
+}
+
+// 多个正弦波叠加，首尾 10ms 淡入淡出避免爆音
+fn tone(freqs: &[f32], duration_ms: u32) -> Vec<i16> {
+    let len = (PROMPT_SAMPLE_RATE * duration_ms / 1000) as usize;
+    let fade = (PROMPT_SAMPLE_RATE / 100) as usize;
+    let amplitude = 0.6 / freqs.len() as f32;
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / PROMPT_SAMPLE_RATE as f32;
+            let envelope = (i.min(len - 1 - i) as f32 / fade as f32).min(1.0);
+            let value: f32 = freqs
+                .iter()
+                .map(|f| (2.0 * std::f32::consts::PI * f * t).sin())
+                .sum();
+            (value * amplitude * envelope * i16::MAX as f32) as i16
+        })
+        .collect()
+}
+
+fn silence(duration_ms: u32) -> Vec<i16> {
+    vec![0; (PROMPT_SAMPLE_RATE * duration_ms / 1000) as usize]
+}
+
+fn write_wav(path: &Path, samples: &[i16]) {
+    let data_len = (samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // 单声道
+    bytes.extend_from_slice(&PROMPT_SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(PROMPT_SAMPLE_RATE * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    fs::write(path, bytes).expect("Failed to write tone prompt");
+}
+
 fn build_or_probe_c_deps(target: &str) {
     let out_dir = env::var("OUT_DIR").unwrap();
     let out_path = Path::new(&out_dir);
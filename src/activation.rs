@@ -1,90 +1,524 @@
-use crate::config::Config;
-use reqwest::Client;
+use crate::clock;
+use crate::config::{Config, MqttConfig};
+use crate::device_info::DeviceInfo;
+use crate::ota;
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, RequestBuilder, StatusCode};
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
+use url::Url;
 
+// 协议 v1 轮询 OTA 接口的间隔
+const OTA_POLL_INTERVAL: Duration = Duration::from_secs(5);
+// 协议 v2 收到 202 后重新提交的间隔
+const ACTIVATE_RETRY_INTERVAL: Duration = Duration::from_secs(3);
+// 服务器未下发 timeout_ms 时 challenge 的有效期
+const DEFAULT_CHALLENGE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// OTA 接口的响应，所有字段都可能缺省
 #[derive(Debug, Deserialize)]
 pub struct ActivationResponse {
+    pub websocket: Option<WebSocketInfo>,
     pub mqtt: Option<MqttConfig>,
+    pub server_time: Option<ServerTime>,
+    pub firmware: Option<FirmwareInfo>,
     pub activation: Option<ActivationInfo>,
 }
 
+/// 服务器下发的 WebSocket 连接参数，覆盖 config 中的 ws_url / ws_token
 #[derive(Debug, Deserialize)]
-pub struct MqttConfig {
-    pub endpoint: String,
-    pub client_id: String,
+pub struct WebSocketInfo {
+    pub url: String,
+    pub token: Option<String>,
+}
+
+impl WebSocketInfo {
+    /// 写入配置，返回配置是否发生变化；url 不合法时忽略
+    pub fn apply(self, config: &mut Config) -> bool {
+        if !Url::parse(&self.url).is_ok_and(|url| matches!(url.scheme(), "ws" | "wss")) {
+            log::warn!("Ignoring invalid websocket url from OTA: {}", self.url);
+            return false;
+        }
+        let token = self.token.unwrap_or_else(|| config.ws_token.to_string());
+        if config.ws_url.as_ref() == self.url && config.ws_token.as_ref() == token {
+            return false;
+        }
+        config.ws_url = Cow::Owned(self.url);
+        config.ws_token = Cow::Owned(token);
+        true
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerTime {
+    /// Unix 时间戳（毫秒）
+    pub timestamp: u64,
+    /// 时区偏移（分钟）
+    #[serde(default)]
+    pub timezone_offset: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FirmwareInfo {
+    pub version: String,
+    #[serde(default)]
+    pub url: String,
+    /// 固件文件的 SHA-256（hex），缺少时不会自动升级
+    pub sha256: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ActivationInfo {
+    #[serde(default)]
     pub code: String,
+    #[serde(default)]
     pub message: String,
+    /// 激活协议 v2：需要用设备密钥签名后提交到 /activate
+    pub challenge: Option<String>,
+    /// challenge 的有效期（毫秒）
+    pub timeout_ms: Option<u64>,
+}
+
+/// 激活协议 v2 的设备身份，从 activation_identity_file 读取
+/// 文件格式：{"serial_number": "...", "hmac_key": "<hex>"}
+#[derive(Deserialize)]
+struct IdentityFile {
+    serial_number: String,
+    hmac_key: String,
+}
+
+pub struct DeviceIdentity {
+    serial_number: String,
+    hmac_key: Vec<u8>,
+}
+
+impl DeviceIdentity {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let metadata =
+            fs::metadata(path).with_context(|| format!("Failed to read {}", path))?;
+        // 文件中含有设备密钥，不应允许其他用户读取
+        if metadata.permissions().mode() & 0o077 != 0 {
+            log::warn!(
+                "Activation identity file {} is accessible by other users (mode {:o}), consider chmod 600",
+                path,
+                metadata.permissions().mode() & 0o777
+            );
+        }
+
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        let file: IdentityFile =
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path))?;
+        let hmac_key = hex::decode(file.hmac_key.trim())
+            .with_context(|| format!("Invalid hmac_key in {}", path))?;
+        if file.serial_number.trim().is_empty() || hmac_key.is_empty() {
+            anyhow::bail!("{} must contain serial_number and hmac_key", path);
+        }
+        Ok(Self {
+            serial_number: file.serial_number.trim().to_string(),
+            hmac_key,
+        })
+    }
+
+    /// 用 HMAC-SHA256 签名服务器下发的 challenge，返回小写 hex
+    fn sign(&self, challenge: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.hmac_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(challenge.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
 }
 
 pub enum ActivationResult {
-    Activated,
+    Activated(Box<ActivationResponse>), // OTA 下发的连接参数等信息
     NeedActivation(String), // 包含 6 位验证码
+    Pending, // 已提交 challenge 签名，等待用户在手机上输入验证码
     Error(String),
 }
 
-pub async fn check_device_activation(config: &Config) -> ActivationResult {
-    // 构造 HTTP URL
-    // 从配置文件读取
-    let http_url = config.ota_url.as_ref();
+// 激活流程的状态
+enum ActivationState {
+    // 轮询 OTA 接口，直到服务器不再要求激活
+    CheckOta,
+    // 协议 v2：向 /activate 提交 challenge 签名，直到服务器确认或 challenge 过期
+    Activate { challenge: String, expires_at: Instant },
+}
+
+/// 激活状态机
+/// 未配置设备身份时按协议 v1 轮询 OTA 接口；配置后收到 challenge 即进入 v2 流程：
+/// 签名提交到 /activate，202 表示用户尚未输入验证码，继续重试，200 表示激活完成
+pub struct Activation<'a> {
+    config: &'a Config,
+    client: &'a Client,
+    identity: Option<DeviceIdentity>,
+    state: ActivationState,
+}
+
+impl<'a> Activation<'a> {
+    pub fn new(config: &'a Config, client: &'a Client) -> anyhow::Result<Self> {
+        let identity = if config.activation_identity_file.is_empty() {
+            None
+        } else {
+            let identity = DeviceIdentity::load(&config.activation_identity_file)?;
+            log::info!("Activation v2 enabled, serial number: {}", identity.serial_number);
+            Some(identity)
+        };
+        Ok(Self {
+            config,
+            client,
+            identity,
+            state: ActivationState::CheckOta,
+        })
+    }
+
+    /// 距下一次调用 step 应等待的时间
+    pub fn retry_delay(&self) -> Duration {
+        match self.state {
+            ActivationState::CheckOta => OTA_POLL_INTERVAL,
+            ActivationState::Activate { .. } => ACTIVATE_RETRY_INTERVAL,
+        }
+    }
+
+    /// 推进一步激活流程
+    pub async fn step(&mut self) -> ActivationResult {
+        if let ActivationState::Activate { challenge, expires_at } = &self.state {
+            if Instant::now() >= *expires_at {
+                log::warn!("Activation challenge expired, requesting a new one");
+            } else {
+                match self.activate(challenge).await {
+                    Ok(false) => return ActivationResult::Pending,
+                    Ok(true) => log::info!("Activation confirmed by server"),
+                    Err(e) => {
+                        self.state = ActivationState::CheckOta;
+                        return ActivationResult::Error(e);
+                    }
+                }
+            }
+            // 激活完成或 challenge 过期，重新查询 OTA 获取连接参数或新的验证码
+            self.state = ActivationState::CheckOta;
+        }
+        self.check_ota().await
+    }
+
+    async fn check_ota(&mut self) -> ActivationResult {
+        let mut response = match self.fetch_ota().await {
+            Ok(response) => response,
+            Err(e) => return ActivationResult::Error(e),
+        };
+
+        // 有 activation.code 说明设备尚未激活
+        if let Some(activation) = response.activation.take().filter(|a| !a.code.is_empty()) {
+            log::info!("Activation required: {}", activation.message);
+            match (&self.identity, activation.challenge) {
+                (Some(_), Some(challenge)) => {
+                    let timeout = activation
+                        .timeout_ms
+                        .map(Duration::from_millis)
+                        .unwrap_or(DEFAULT_CHALLENGE_TIMEOUT);
+                    self.state = ActivationState::Activate {
+                        challenge,
+                        expires_at: Instant::now() + timeout,
+                    };
+                }
+                (None, Some(_)) => {
+                    log::warn!("Server sent an activation challenge but activation_identity_file is not configured");
+                }
+                _ => {}
+            }
+            return ActivationResult::NeedActivation(activation.code);
+        }
+
+        // 忽略未填写 endpoint 的 mqtt 字段
+        response.mqtt = response.mqtt.filter(MqttConfig::is_configured);
+        ActivationResult::Activated(Box::new(response))
+    }
+
+    // 请求 OTA 接口，获取激活状态和连接参数
+    async fn fetch_ota(&self) -> Result<ActivationResponse, String> {
+        let config = self.config;
+        // 构造 HTTP URL
+        // 从配置文件读取
+        let http_url = config.ota_url.as_ref();
+
+        log::info!("Checking activation status via HTTP: {}", http_url);
 
-    let client = Client::new();
+        // 构造请求体
+        let mut body = json!({
+            "uuid": config.client_id,
+            "application": {
+                "name": env!("APP_NAME"),
+                "version": env!("APP_VERSION")
+            },
+            "ota": ota::ota_report(),
+            "board": {
+                "type": env!("BOARD_TYPE"),
+                "name": env!("BOARD_NAME")
+            }
+        });
+        // 附带设备信息（MAC、CPU、内存、网络等），供服务器做设备管理
+        let device_info = DeviceInfo::collect(&config.device_info_exclude);
+        if let (Some(body), Ok(Value::Object(info))) =
+            (body.as_object_mut(), serde_json::to_value(device_info))
+        {
+            body.extend(info);
+        }
 
-    log::info!("Checking activation status via HTTP: {}", http_url);
+        let resp = self
+            .request(http_url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+        if !resp.status().is_success() {
+            return Err(format!("HTTP Error: {}", resp.status()));
+        }
 
-    // 构造请求体
-    let body = json!({
-        "uuid": config.client_id,
-        "application": {
-            "name": env!("APP_NAME"),
-            "version": env!("APP_VERSION")
-        },
-        "ota": {},
-        "board": {
-            "type": env!("BOARD_TYPE"),
-            "name": env!("BOARD_NAME")
+        // 解析 JSON
+        let response = resp
+            .json::<ActivationResponse>()
+            .await
+            .map_err(|e| format!("JSON parse error: {}", e))?;
+        ota::clear_rollback_report();
+        // 无 RTC 的板子靠服务器时间校准时钟
+        if let Some(time) = &response.server_time {
+            clock::sync(time.timestamp, time.timezone_offset);
+            if config.enable_system_time_sync {
+                clock::apply_to_system();
+            }
         }
-    });
+        if let Some(firmware) = &response.firmware {
+            log::info!(
+                "Latest firmware: {} (current {}) {}",
+                firmware.version,
+                env!("APP_VERSION"),
+                firmware.url
+            );
+        }
+        Ok(response)
+    }
+
+    // 提交 challenge 签名，返回是否激活完成（202 表示用户尚未输入验证码）
+    async fn activate(&self, challenge: &str) -> Result<bool, String> {
+        let Some(identity) = &self.identity else {
+            return Err("Activation identity not configured".to_string());
+        };
+        let url = format!("{}/activate", self.config.ota_url.trim_end_matches('/'));
+        let body = json!({
+            "algorithm": "hmac-sha256",
+            "serial_number": identity.serial_number,
+            "challenge": challenge,
+            "hmac": identity.sign(challenge),
+        });
+
+        log::info!("Submitting activation challenge to {}", url);
+        let resp = self
+            .request(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("Activate request failed: {}", e))?;
+        match resp.status() {
+            StatusCode::OK => Ok(true),
+            StatusCode::ACCEPTED => Ok(false),
+            status => {
+                let text = resp.text().await.unwrap_or_default();
+                Err(format!("Activate failed: HTTP {} {}", status, text))
+            }
+        }
+    }
 
     // 构造请求
     // 参考 C++ control_center.cpp 中的 headers
     // 不包含 Authorization 和 Protocol-Version
-    let response = client
-        .post(http_url)
-        .header("Device-Id", &config.device_id)
-        .header("Content-Type", "application/json")
-        .header("User-Agent", "weidongshan1")
-        .header("Accept-Language", "zh-CN")
-        .json(&body)
-        .send()
+    fn request(&self, url: &str) -> RequestBuilder {
+        let mut request = self
+            .client
+            .post(url)
+            .header("Device-Id", &self.config.device_id)
+            .header("Content-Type", "application/json")
+            .header("User-Agent", "weidongshan1")
+            .header("Accept-Language", "zh-CN");
+        request = match &self.identity {
+            Some(identity) => request
+                .header("Activation-Version", "2")
+                .header("Serial-Number", &identity.serial_number),
+            None => request.header("Activation-Version", "1"),
+        };
+        request
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    const CHALLENGE: &str = "what do ya want for nothing?";
+
+    fn config(server: &TestServer) -> Config {
+        Config {
+            ota_url: Cow::Owned(format!("{}/ota/", server.url)),
+            ..Config::default()
+        }
+    }
+
+    fn activation<'a>(config: &'a Config, client: &'a Client, v2: bool) -> Activation<'a> {
+        Activation {
+            config,
+            client,
+            identity: v2.then(|| DeviceIdentity {
+                serial_number: "SN-0001".to_string(),
+                hmac_key: b"Jefe".to_vec(),
+            }),
+            state: ActivationState::CheckOta,
+        }
+    }
+
+    fn need_activation(challenge: Option<&str>, timeout_ms: u64) -> (u16, Vec<u8>) {
+        TestServer::json(200, json!({
+            "activation": {
+                "code": "123456",
+                "message": "请在手机上输入验证码",
+                "challenge": challenge,
+                "timeout_ms": timeout_ms,
+            }
+        }))
+    }
+
+    fn activated() -> (u16, Vec<u8>) {
+        TestServer::json(200, json!({
+            "websocket": {"url": "wss://example.com/ws", "token": "token"},
+            "firmware": {"version": "0.0.1", "url": ""},
+        }))
+    }
+
+    #[test]
+    fn signs_challenge_with_hmac_sha256() {
+        let identity = DeviceIdentity {
+            serial_number: "SN-0001".to_string(),
+            hmac_key: b"Jefe".to_vec(),
+        };
+        // RFC 4231 test case 2
+        assert_eq!(
+            identity.sign(CHALLENGE),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn v1_polls_until_code_disappears() {
+        let server = TestServer::start(vec![
+            need_activation(None, 0),
+            need_activation(None, 0),
+            activated(),
+        ])
         .await;
+        let config = config(&server);
+        let client = Client::new();
+        let mut activation = activation(&config, &client, false);
 
-    match response {
-        Ok(resp) => {
-            if resp.status().is_success() {
-                // 解析 JSON
-                match resp.json::<serde_json::Value>().await {
-                    Ok(json) => {
-                        // 检查是否有 "activation" 字段
-                        if let Some(activation) = json.get("activation") {
-                            if let Some(code) = activation.get("code") {
-                                let code_str = code.as_str().unwrap_or("").to_string();
-                                return ActivationResult::NeedActivation(code_str);
-                            }
-                        }
-                        // 如果没有 activation 字段，或者字段为空，视为已激活
-                        return ActivationResult::Activated;
-                    }
-                    Err(e) => ActivationResult::Error(format!("JSON parse error: {}", e)),
-                }
-            } else {
-                ActivationResult::Error(format!("HTTP Error: {}", resp.status()))
+        for _ in 0..2 {
+            match activation.step().await {
+                ActivationResult::NeedActivation(code) => assert_eq!(code, "123456"),
+                _ => panic!("expected NeedActivation"),
             }
+            assert_eq!(activation.retry_delay(), OTA_POLL_INTERVAL);
+        }
+        let ActivationResult::Activated(response) = activation.step().await else {
+            panic!("expected Activated");
+        };
+        assert_eq!(response.websocket.unwrap().url, "wss://example.com/ws");
+
+        assert_eq!(server.paths(), ["/ota/", "/ota/", "/ota/"]);
+        let request = &server.requests()[0];
+        assert_eq!(request.header("Activation-Version"), Some("1"));
+        assert_eq!(request.json()["application"]["version"], env!("APP_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn v2_submits_signature_until_confirmed() {
+        let server = TestServer::start(vec![
+            need_activation(Some(CHALLENGE), 60_000),
+            (202, Vec::new()),
+            (200, Vec::new()),
+            activated(),
+        ])
+        .await;
+        let config = config(&server);
+        let client = Client::new();
+        let mut activation = activation(&config, &client, true);
+
+        assert!(matches!(activation.step().await, ActivationResult::NeedActivation(_)));
+        assert_eq!(activation.retry_delay(), ACTIVATE_RETRY_INTERVAL);
+        assert!(matches!(activation.step().await, ActivationResult::Pending));
+        // 200 之后重新查询 OTA 获取连接参数
+        assert!(matches!(activation.step().await, ActivationResult::Activated(_)));
+        assert_eq!(activation.retry_delay(), OTA_POLL_INTERVAL);
+
+        assert_eq!(server.paths(), ["/ota/", "/ota/activate", "/ota/activate", "/ota/"]);
+        let request = &server.requests()[1];
+        assert_eq!(request.header("Activation-Version"), Some("2"));
+        assert_eq!(request.header("Serial-Number"), Some("SN-0001"));
+        assert_eq!(
+            request.json(),
+            json!({
+                "algorithm": "hmac-sha256",
+                "serial_number": "SN-0001",
+                "challenge": CHALLENGE,
+                "hmac": "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_challenge_requests_a_new_one() {
+        let server = TestServer::start(vec![
+            need_activation(Some("first"), 0),
+            need_activation(Some("second"), 60_000),
+            (202, Vec::new()),
+        ])
+        .await;
+        let config = config(&server);
+        let client = Client::new();
+        let mut activation = activation(&config, &client, true);
+
+        assert!(matches!(activation.step().await, ActivationResult::NeedActivation(_)));
+        assert!(matches!(activation.step().await, ActivationResult::NeedActivation(_)));
+        assert!(matches!(activation.step().await, ActivationResult::Pending));
+
+        assert_eq!(server.paths(), ["/ota/", "/ota/", "/ota/activate"]);
+        assert_eq!(server.requests()[2].json()["challenge"], "second");
+    }
+
+    #[tokio::test]
+    async fn non_success_status_is_an_error() {
+        let server = TestServer::start(vec![
+            (503, Vec::new()),
+            need_activation(Some(CHALLENGE), 60_000),
+            (403, b"bad signature".to_vec()),
+            activated(),
+        ])
+        .await;
+        let config = config(&server);
+        let client = Client::new();
+        let mut activation = activation(&config, &client, true);
+
+        match activation.step().await {
+            ActivationResult::Error(e) => assert!(e.contains("503"), "{e}"),
+            _ => panic!("expected Error"),
+        }
+        assert!(matches!(activation.step().await, ActivationResult::NeedActivation(_)));
+        match activation.step().await {
+            ActivationResult::Error(e) => assert!(e.contains("403") && e.contains("bad signature")),
+            _ => panic!("expected Error"),
         }
-        Err(e) => ActivationResult::Error(format!("Request failed: {}", e)),
+        // 提交失败后回到查询 OTA
+        assert!(matches!(activation.step().await, ActivationResult::Activated(_)));
+        assert_eq!(server.paths(), ["/ota/", "/ota/", "/ota/activate", "/ota/"]);
     }
 }
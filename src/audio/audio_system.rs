@@ -3,15 +3,17 @@
 //! Uses std::thread (NOT tokio tasks) for real-time audio I/O to avoid
 //! contention with async network tasks.
 
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 use tokio::sync::mpsc;
 
 use anyhow::Result;
 
+use super::echo_reference::EchoReference;
 use super::record::record_thread;
 use super::play::play_thread;
+use super::prompt::PcmFormat;
 
 /// Audio system configuration.
 #[derive(Debug, Clone)]
@@ -36,12 +38,38 @@ pub struct AudioConfig {
     pub decode_frame_duration_ms: u32,
     /// 网络下发流的编码格式: "opus", "mp3", "pcm"
     pub stream_format: String,
+    /// Source sample rate of a raw PCM stream (stream_format = "pcm")
+    pub pcm_sample_rate: u32,
+    /// Source channel count of a raw PCM stream
+    pub pcm_channels: u32,
+    /// Whether raw PCM samples are big-endian (default little-endian)
+    pub pcm_big_endian: bool,
     /// Desired ALSA playback sample rate
     pub playback_sample_rate: u32,
     /// Desired ALSA playback channel count
     pub playback_channels: u32,
     /// Desired ALSA playback period size (0 = let ALSA decide)
     pub playback_period_size: usize,
+    /// Enable Speex acoustic echo cancellation on the capture path
+    pub aec_enabled: bool,
+    /// Echo canceller filter (tail) length in ms
+    pub aec_filter_length_ms: u32,
+    /// Run the energy detector on captured audio and report speech onsets
+    pub barge_in_enabled: bool,
+    /// Speech onset level threshold in dBFS
+    pub barge_in_threshold_db: i32,
+    /// Minimum duration above the threshold to report an onset, in ms
+    pub barge_in_min_speech_ms: u32,
+    /// Silence after speech before the VAD reports the end of speech, in ms
+    pub vad_hangover_ms: u32,
+    /// Run the local wake-word detector on captured audio
+    pub wake_word_enabled: bool,
+    /// Text reported when the wake word fires
+    pub wake_word: String,
+    /// WAV recordings of the wake word used as matching templates
+    pub wake_word_templates: Vec<String>,
+    /// Detection threshold (maximum normalised DTW cost)
+    pub wake_word_threshold: f32,
 }
 
 impl Default for AudioConfig {
@@ -57,19 +85,134 @@ impl Default for AudioConfig {
             encode_frame_duration_ms: 60,
             decode_frame_duration_ms: 20,
             stream_format: "opus".to_string(),
+            pcm_sample_rate: 24000,
+            pcm_channels: 1,
+            pcm_big_endian: false,
             playback_sample_rate: 48000,
             playback_channels: 2,
             playback_period_size: 1024,
+            aec_enabled: false,
+            aec_filter_length_ms: 200,
+            barge_in_enabled: false,
+            barge_in_threshold_db: -35,
+            barge_in_min_speech_ms: 300,
+            vad_hangover_ms: 800,
+            wake_word_enabled: false,
+            wake_word: String::new(),
+            wake_word_templates: Vec::new(),
+            wake_word_threshold: 5.0,
         }
     }
 }
 
+/// Format of the downstream stream fed to the playback decoder.
+///
+/// Used for Opus and raw PCM streams; MP3 frames describe themselves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecoderParams {
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// Frame duration in ms (Opus only)
+    pub frame_duration_ms: u32,
+}
+
+impl DecoderParams {
+    /// Stream parameters configured at startup.
+    pub fn from_config(config: &AudioConfig) -> Self {
+        match config.stream_format.as_str() {
+            "pcm" => Self {
+                sample_rate: config.pcm_sample_rate,
+                channels: config.pcm_channels,
+                frame_duration_ms: config.decode_frame_duration_ms,
+            },
+            _ => Self {
+                sample_rate: config.opus_sample_rate,
+                channels: config.opus_channels,
+                frame_duration_ms: config.decode_frame_duration_ms,
+            },
+        }
+    }
+}
+
+/// Decoder parameters shared with the playback thread.
+///
+/// `update()` records new parameters; the playback thread picks them up with
+/// `take_update()` before decoding its next packet and rebuilds the decoder.
+pub struct DecoderControl {
+    params: Mutex<DecoderParams>,
+    changed: AtomicBool,
+}
+
+impl DecoderControl {
+    pub fn new(params: DecoderParams) -> Self {
+        Self {
+            params: Mutex::new(params),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Store new parameters. Returns false if they match the current ones.
+    pub fn update(&self, params: DecoderParams) -> bool {
+        let mut current = self.params.lock().unwrap();
+        if *current == params {
+            return false;
+        }
+        *current = params;
+        self.changed.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// Parameters set since the last call, if any.
+    pub fn take_update(&self) -> Option<DecoderParams> {
+        if self.changed.swap(false, Ordering::SeqCst) {
+            Some(*self.params.lock().unwrap())
+        } else {
+            None
+        }
+    }
+}
+
+/// Events produced by the recording thread.
+#[derive(Debug)]
+pub enum CaptureEvent {
+    /// One encoded Opus packet, tagged with the timestamp of the playback
+    /// packet being played when it was captured (0 if none), used by the
+    /// server to align its echo cancellation
+    Encoded { data: Vec<u8>, timestamp: u32 },
+    /// Sustained speech detected by the energy detector (barge-in)
+    BargeIn,
+    /// The Speex VAD entered the speech state
+    SpeechStart,
+    /// The Speex VAD stayed silent for the hangover time after speech
+    SpeechEnd,
+    /// The local wake-word detector fired, carrying the wake word text
+    WakeWord(String),
+}
+
+/// An encoded packet queued for playback.
+///
+/// Tagged with the playback generation current when it was queued; packets
+/// from an older generation were queued before `stop_playback()` and are
+/// discarded by the playback thread. `timestamp` is the server timestamp of
+/// the packet (0 if the protocol carries none). Local voice prompts set
+/// `pcm_format`: their `data` is little-endian s16 PCM in that format rather
+/// than the negotiated stream format.
+#[derive(Debug)]
+pub struct PlaybackPacket {
+    pub generation: u64,
+    pub timestamp: u32,
+    pub data: Vec<u8>,
+    pub pcm_format: Option<PcmFormat>,
+}
+
 /// The audio system manages recording and playback in dedicated OS threads.
 ///
-/// - Recording thread: ALSA capture → Speex preprocess → Opus encode → `opus_tx`
-/// - Playback thread: `opus_rx` → Opus decode → ALSA playback
+/// - Recording thread: ALSA capture → (AEC) → Speex preprocess → (wake word) → Opus encode → `capture_tx`
+/// - Playback thread: `play_rx` → decode → ALSA playback (→ AEC reference)
 pub struct AudioSystem {
     running: Arc<AtomicBool>,
+    playback_generation: Arc<AtomicU64>,
+    decoder_control: Arc<DecoderControl>,
     record_handle: Option<JoinHandle<()>>,
     play_handle: Option<JoinHandle<()>>,
 }
@@ -77,15 +220,25 @@ pub struct AudioSystem {
 impl AudioSystem {
     /// Start the audio system.
     ///
-    /// * `config`  - Audio configuration
-    /// * `opus_tx` - Sender for encoded Opus packets from recording
-    /// * `opus_rx` - Receiver for Opus packets to decode and play
+    /// * `config`     - Audio configuration
+    /// * `capture_tx` - Sender for encoded Opus packets and speech events from recording
+    /// * `play_rx`    - Receiver for packets to decode and play
     pub fn start(
         config: AudioConfig,
-        opus_tx: mpsc::Sender<Vec<u8>>,
-        opus_rx: mpsc::Receiver<Vec<u8>>,
+        capture_tx: mpsc::Sender<CaptureEvent>,
+        play_rx: mpsc::Receiver<PlaybackPacket>,
     ) -> Result<Self> {
         let running = Arc::new(AtomicBool::new(true));
+        let playback_generation = Arc::new(AtomicU64::new(0));
+        // Timestamp of the last packet written to ALSA, read by the recording
+        // thread to tag encoded packets
+        let playback_timestamp = Arc::new(AtomicU32::new(0));
+        let decoder_control = Arc::new(DecoderControl::new(DecoderParams::from_config(&config)));
+
+        // Speaker reference shared by both threads when AEC is enabled
+        let echo_reference = config
+            .aec_enabled
+            .then(|| Arc::new(EchoReference::default()));
 
         log::info!(
             "AudioSystem starting — capture: \"{}\", playback: \"{}\", rate: {}Hz, ch: {}, opus: {}Hz/{}ch",
@@ -96,14 +249,25 @@ impl AudioSystem {
             config.opus_sample_rate,
             config.opus_channels,
         );
+        if config.aec_enabled {
+            log::info!("AEC enabled, filter length {}ms", config.aec_filter_length_ms);
+        }
 
         let record_handle = {
             let running = running.clone();
             let config = config.clone();
+            let echo_reference = echo_reference.clone();
+            let playback_timestamp = playback_timestamp.clone();
             thread::Builder::new()
                 .name("audio-record".into())
                 .spawn(move || {
-                    if let Err(e) = record_thread(&config, opus_tx, &running) {
+                    if let Err(e) = record_thread(
+                        &config,
+                        capture_tx,
+                        echo_reference.as_deref(),
+                        &playback_timestamp,
+                        &running,
+                    ) {
                         log::error!("Recording thread error: {}", e);
                     }
                 })?
@@ -111,13 +275,25 @@ impl AudioSystem {
 
         let play_handle = {
             let running = running.clone();
+            let playback_generation = playback_generation.clone();
+            let decoder_control = decoder_control.clone();
             let config = config.clone();
             thread::Builder::new()
                 .name("audio-play".into())
                 .spawn(move || {
                     // Small delay to let capture device initialize first
                     thread::sleep(std::time::Duration::from_secs(1));
-                    if let Err(e) = play_thread(&config, opus_rx, &running) {
+                    if let Err(e) =
+                        play_thread(
+                            &config,
+                            play_rx,
+                            &playback_generation,
+                            &playback_timestamp,
+                            &decoder_control,
+                            echo_reference.as_deref(),
+                            &running,
+                        )
+                    {
                         log::error!("Playback thread error: {}", e);
                     }
                 })?
@@ -125,11 +301,49 @@ impl AudioSystem {
 
         Ok(Self {
             running,
+            playback_generation,
+            decoder_control,
             record_handle: Some(record_handle),
             play_handle: Some(play_handle),
         })
     }
 
+    /// An audio system without recording and playback threads, for tests
+    /// that only need the playback generation and decoder control.
+    #[cfg(test)]
+    pub fn detached(config: &AudioConfig) -> Self {
+        Self {
+            running: Arc::new(AtomicBool::new(true)),
+            playback_generation: Arc::new(AtomicU64::new(0)),
+            decoder_control: Arc::new(DecoderControl::new(DecoderParams::from_config(config))),
+            record_handle: None,
+            play_handle: None,
+        }
+    }
+
+    /// Current playback generation, used to tag newly queued packets.
+    pub fn playback_generation(&self) -> u64 {
+        self.playback_generation.load(Ordering::SeqCst)
+    }
+
+    /// Discard everything queued for playback so far.
+    ///
+    /// Packets tagged with an older generation are dropped by the playback
+    /// thread instead of being decoded. On noticing the new generation the
+    /// playback thread drops the ALSA buffer and resets the decoder. Returns
+    /// the new generation.
+    pub fn stop_playback(&self) -> u64 {
+        self.playback_generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Switch the playback decoder to a new downstream stream format.
+    ///
+    /// Takes effect before the next packet is decoded. Returns false if the
+    /// parameters are unchanged.
+    pub fn reconfigure_decoder(&self, params: DecoderParams) -> bool {
+        self.decoder_control.update(params)
+    }
+
     /// Signal threads to stop and wait for them to finish.
     pub fn stop(&mut self) {
         self.running.store(false, Ordering::SeqCst);
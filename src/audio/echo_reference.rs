@@ -0,0 +1,124 @@
+//! Speaker reference signal shared between the playback and recording threads.
+//!
+//! The playback thread pushes what it writes to ALSA (downmixed to mono and
+//! resampled to the capture rate); the recording thread pops one capture
+//! period at a time and feeds it to the echo canceller alongside the mic
+//! signal. Because samples are pushed when written into the ALSA buffer, the
+//! reference leads the acoustic echo by the playback latency, which the echo
+//! canceller's filter tail has to cover.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Maximum amount of queued reference audio, in milliseconds.
+/// Older samples are dropped so a stalled capture thread cannot grow the queue.
+const MAX_QUEUED_MS: usize = 1000;
+
+#[derive(Default)]
+pub struct EchoReference {
+    samples: Mutex<VecDeque<i16>>,
+    /// Negotiated capture sample rate (0 until the capture device is open)
+    capture_rate: AtomicU32,
+}
+
+impl EchoReference {
+    /// Publish the negotiated capture sample rate the reference must match.
+    pub fn set_capture_rate(&self, rate: u32) {
+        self.capture_rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// Capture sample rate, or 0 if recording has not started yet.
+    pub fn capture_rate(&self) -> u32 {
+        self.capture_rate.load(Ordering::Relaxed)
+    }
+
+    /// Append mono reference samples at the capture rate.
+    pub fn push(&self, reference: &[i16]) {
+        let max_len = self.capture_rate() as usize * MAX_QUEUED_MS / 1000;
+        let mut samples = self.samples.lock().unwrap();
+        samples.extend(reference.iter().copied());
+        if samples.len() > max_len {
+            let excess = samples.len() - max_len;
+            samples.drain(..excess);
+        }
+    }
+
+    /// Drop all queued reference audio (playback was flushed).
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+
+    /// Fill `out` with the next reference samples, padding with silence
+    /// when nothing is playing.
+    pub fn pop_into(&self, out: &mut [i16]) {
+        let mut samples = self.samples.lock().unwrap();
+        let n = out.len().min(samples.len());
+        for (dst, src) in out.iter_mut().zip(samples.drain(..n)) {
+            *dst = src;
+        }
+        out[n..].fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(rate: u32) -> EchoReference {
+        let reference = EchoReference::default();
+        reference.set_capture_rate(rate);
+        reference
+    }
+
+    fn pop(reference: &EchoReference, len: usize) -> Vec<i16> {
+        let mut out = vec![-1; len];
+        reference.pop_into(&mut out);
+        out
+    }
+
+    #[test]
+    fn pops_in_capture_period_order() {
+        let reference = reference(16000);
+        reference.push(&[1, 2, 3]);
+        reference.push(&[4, 5]);
+        assert_eq!(pop(&reference, 2), [1, 2]);
+        assert_eq!(pop(&reference, 2), [3, 4]);
+        assert_eq!(pop(&reference, 2), [5, 0]);
+    }
+
+    #[test]
+    fn pads_with_silence_on_underrun() {
+        let reference = reference(16000);
+        assert_eq!(pop(&reference, 4), [0; 4]);
+        reference.push(&[7]);
+        assert_eq!(pop(&reference, 4), [7, 0, 0, 0]);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_second() {
+        let reference = reference(100);
+        let samples: Vec<i16> = (0..250).collect();
+        reference.push(&samples[..150]);
+        reference.push(&samples[150..]);
+
+        assert_eq!(pop(&reference, 100), samples[150..]);
+        assert_eq!(pop(&reference, 1), [0]);
+    }
+
+    #[test]
+    fn drops_reference_until_capture_starts() {
+        let reference = EchoReference::default();
+        reference.push(&[1, 2, 3]);
+        reference.set_capture_rate(16000);
+        assert_eq!(pop(&reference, 3), [0; 3]);
+    }
+
+    #[test]
+    fn clear_discards_queued_audio() {
+        let reference = reference(16000);
+        reference.push(&[1, 2, 3]);
+        reference.clear();
+        assert_eq!(pop(&reference, 3), [0; 3]);
+    }
+}
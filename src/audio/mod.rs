@@ -1,16 +1,25 @@
 //! audio - Audio capture, playback, and codec library
 //!
 //! Replaces the external C++ sound_app process with an integrated Rust library.
-//! Uses ALSA for audio I/O, Opus for encoding/decoding, and SpeexDSP
-//! for noise suppression, AGC, and resampling.
+//! Uses ALSA for audio I/O, Opus for encoding/decoding, MP3 for playback
+//! decoding, SpeexDSP for echo cancellation, noise suppression, AGC,
+//! and resampling, and a pluggable local wake-word detection stage.
 
 mod alsa_device;
 mod audio_system;
+mod echo_reference;
+mod mp3_decoder;
 mod opus_codec;
+mod pcm_decoder;
 mod play;
+mod prompt;
 mod record;
 mod speex;
 pub mod stream_decoder;
+mod template_kws;
+mod vad;
+mod wake_word;
+mod wav;
 
-pub use audio_system::{AudioConfig, AudioSystem};
-pub use stream_decoder::StreamDecoder;
+pub use audio_system::{AudioConfig, AudioSystem, CaptureEvent, DecoderParams, PlaybackPacket};
+pub use prompt::{PromptClip, PromptClips};
@@ -0,0 +1,374 @@
+//! MP3 stream decoder with frame resync, resampling and channel conversion.
+//!
+//! WebSocket binary messages are not aligned to MP3 frames: a frame may be
+//! split across messages, and a message may start with an ID3 tag or garbage.
+//! Incoming bytes are buffered, frame headers are located by sync word, and
+//! only complete frames are handed to the decoder.
+
+use anyhow::Result;
+use symphonia_bundle_mp3::MpaDecoder;
+use symphonia_core::audio::SampleBuffer;
+use symphonia_core::codecs::{CODEC_TYPE_MP3, CodecParameters, Decoder, DecoderOptions};
+use symphonia_core::formats::Packet;
+
+use super::stream_decoder::{PcmConverter, StreamDecoder};
+
+/// Size of an MPEG audio frame header in bytes.
+const HEADER_SIZE: usize = 4;
+/// Size of an ID3v2 tag header in bytes.
+const ID3V2_HEADER_SIZE: usize = 10;
+/// Upper bound of buffered bytes while waiting for more data (a partial frame
+/// or a large ID3v2 tag).
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+
+/// Layer III bitrates in kbps, indexed by the 4-bit bitrate index.
+const BITRATES_MPEG1: [u32; 15] = [
+    0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+const BITRATES_MPEG2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// Parsed fields of a Layer III frame header needed for framing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FrameHeader {
+    sample_rate: u32,
+    channels: u32,
+    /// Total frame length in bytes, header included
+    frame_len: usize,
+}
+
+impl FrameHeader {
+    /// Parse a 4-byte MPEG audio header. Returns `None` for anything that is
+    /// not a valid MPEG-1/2/2.5 Layer III header (free format is rejected
+    /// since its frame length cannot be derived from the header).
+    fn parse(h: &[u8]) -> Option<Self> {
+        if h.len() < HEADER_SIZE || h[0] != 0xFF || (h[1] & 0xE0) != 0xE0 {
+            return None;
+        }
+
+        let version = (h[1] >> 3) & 0x03; // 00 = 2.5, 01 = reserved, 10 = 2, 11 = 1
+        let layer = (h[1] >> 1) & 0x03; // 01 = Layer III
+        let bitrate_index = (h[2] >> 4) as usize;
+        let sample_rate_index = ((h[2] >> 2) & 0x03) as usize;
+        let padding = ((h[2] >> 1) & 0x01) as usize;
+        let channel_mode = (h[3] >> 6) & 0x03; // 11 = mono
+
+        if version == 0x01 || layer != 0x01 {
+            return None;
+        }
+        if bitrate_index == 0 || bitrate_index == 0x0F || sample_rate_index == 0x03 {
+            return None;
+        }
+
+        let (bitrate_kbps, sample_rate, samples_per_frame) = match version {
+            0x03 => (
+                BITRATES_MPEG1[bitrate_index],
+                [44100, 48000, 32000][sample_rate_index],
+                1152,
+            ),
+            0x02 => (
+                BITRATES_MPEG2[bitrate_index],
+                [22050, 24000, 16000][sample_rate_index],
+                576,
+            ),
+            _ => (
+                BITRATES_MPEG2[bitrate_index],
+                [11025, 12000, 8000][sample_rate_index],
+                576,
+            ),
+        };
+
+        let frame_len =
+            (samples_per_frame / 8 * bitrate_kbps * 1000 / sample_rate) as usize + padding;
+
+        Some(Self {
+            sample_rate,
+            channels: if channel_mode == 0x03 { 1 } else { 2 },
+            frame_len,
+        })
+    }
+}
+
+pub struct Mp3Decoder {
+    decoder: MpaDecoder,
+    /// Created on the first frame, rebuilt if the stream format changes
+    converter: Option<PcmConverter>,
+    /// Bytes received but not yet consumed as a complete frame
+    pending: Vec<u8>,
+    /// Whether the last frame decoded cleanly at the expected position
+    synced: bool,
+    output_sample_rate: u32,
+    output_channels: u32,
+}
+
+impl Mp3Decoder {
+    /// Create a new MP3 decoder.
+    ///
+    /// The source sample rate and channel count are taken from the frame
+    /// headers, so only the playback side needs to be known up front.
+    ///
+    /// * `output_sample_rate` - ALSA playback sample rate
+    /// * `output_channels`    - ALSA playback channels
+    pub fn new(output_sample_rate: u32, output_channels: u32) -> Result<Self> {
+        Ok(Self {
+            decoder: Self::new_codec()?,
+            converter: None,
+            pending: Vec::with_capacity(4096),
+            synced: false,
+            output_sample_rate,
+            output_channels,
+        })
+    }
+
+    fn new_codec() -> Result<MpaDecoder> {
+        let mut params = CodecParameters::new();
+        params.for_codec(CODEC_TYPE_MP3);
+        MpaDecoder::try_new(&params, &DecoderOptions::default())
+            .map_err(|e| anyhow::anyhow!("Failed to create MP3 decoder: {}", e))
+    }
+
+    /// Skip a leading ID3v2 tag. Returns `false` if more data is needed.
+    fn skip_id3v2(&mut self) -> bool {
+        if !self.pending.starts_with(b"ID3") {
+            return true;
+        }
+        if self.pending.len() < ID3V2_HEADER_SIZE {
+            return false;
+        }
+        // Tag size is a 28-bit syncsafe integer excluding the 10-byte header
+        let size = self.pending[6..10]
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 7) | (b & 0x7F) as usize);
+        let footer = if self.pending[5] & 0x10 != 0 { 10 } else { 0 };
+        let total = ID3V2_HEADER_SIZE + size + footer;
+        if self.pending.len() < total {
+            return false;
+        }
+        log::debug!("Skipping ID3v2 tag ({} bytes)", total);
+        self.pending.drain(..total);
+        true
+    }
+
+    /// Locate the next frame header in `pending`, discarding bytes before it.
+    ///
+    /// When not yet synced, a candidate is only accepted if the following
+    /// header (when already buffered) is consistent with it, to avoid locking
+    /// onto a false sync word inside audio data.
+    fn find_frame(&mut self) -> Option<FrameHeader> {
+        let mut offset = 0;
+        let found = loop {
+            if offset + HEADER_SIZE > self.pending.len() {
+                break None;
+            }
+            if let Some(header) = FrameHeader::parse(&self.pending[offset..]) {
+                if self.synced {
+                    break Some(header);
+                }
+                let next = offset + header.frame_len;
+                match self.pending.get(next..next + HEADER_SIZE) {
+                    Some(next_bytes) => match FrameHeader::parse(next_bytes) {
+                        Some(n) if n.sample_rate == header.sample_rate => break Some(header),
+                        _ => {}
+                    },
+                    // Next header not buffered yet, accept tentatively
+                    None => break Some(header),
+                }
+            }
+            self.synced = false;
+            offset += 1;
+        };
+
+        if offset > 0 {
+            log::debug!("MP3 resync: skipped {} bytes", offset);
+            self.pending.drain(..offset);
+        }
+        found
+    }
+
+    /// Decode one complete frame (header included) to interleaved PCM
+    /// at the source sample rate and channel count.
+    fn decode_frame(&mut self, frame: &[u8]) -> Result<Vec<i16>> {
+        let packet = Packet::new_from_slice(0, 0, 0, frame);
+        let decoded = self
+            .decoder
+            .decode(&packet)
+            .map_err(|e| anyhow::anyhow!("MP3 decode error: {}", e))?;
+
+        let spec = *decoded.spec();
+        let mut sample_buf = SampleBuffer::<i16>::new(decoded.capacity() as u64, spec);
+        sample_buf.copy_interleaved_ref(decoded);
+        Ok(sample_buf.samples().to_vec())
+    }
+}
+
+impl StreamDecoder for Mp3Decoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<i16>> {
+        self.pending.extend_from_slice(data);
+
+        let mut out = Vec::new();
+        loop {
+            if !self.skip_id3v2() {
+                break;
+            }
+            let Some(header) = self.find_frame() else {
+                break;
+            };
+            if self.pending.len() < header.frame_len {
+                // Frame split across WebSocket messages, wait for the rest
+                break;
+            }
+
+            let frame: Vec<u8> = self.pending.drain(..header.frame_len).collect();
+
+            // Source format changed mid-stream (e.g. new TTS session at a different
+            // rate): the codec cannot switch signal spec, so start over.
+            let format_changed = self
+                .converter
+                .as_ref()
+                .is_some_and(|c| !c.matches_input(header.sample_rate, header.channels));
+            if format_changed {
+                log::info!(
+                    "MP3 stream format changed to {}Hz/{}ch, resetting decoder",
+                    header.sample_rate,
+                    header.channels
+                );
+                self.decoder = Self::new_codec()?;
+                self.converter = None;
+            }
+            if self.converter.is_none() {
+                self.converter = Some(PcmConverter::new(
+                    header.sample_rate,
+                    header.channels,
+                    self.output_sample_rate,
+                    self.output_channels,
+                )?);
+            }
+
+            match self.decode_frame(&frame) {
+                Ok(pcm) => {
+                    self.synced = true;
+                    if let Some(converter) = self.converter.as_mut() {
+                        out.extend(converter.process(&pcm)?);
+                    }
+                }
+                Err(e) => {
+                    // Likely a false sync or a frame depending on a lost bit reservoir;
+                    // drop it and search for the next header.
+                    log::warn!("{}", e);
+                    self.synced = false;
+                }
+            }
+        }
+
+        // Without a sync word the resync above keeps only a few bytes, so the
+        // buffer can only grow this large while waiting for a huge ID3v2 tag
+        if self.pending.len() > MAX_PENDING_BYTES {
+            log::warn!(
+                "MP3 stream buffered {} bytes without a complete frame, discarding",
+                self.pending.len()
+            );
+            self.pending.clear();
+            self.synced = false;
+        }
+
+        Ok(out)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.pending.clear();
+        self.synced = false;
+        self.decoder.reset();
+        // The next stream may use a different source format
+        self.converter = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MPEG-1 Layer III, 128 kbps, 44.1 kHz, mono, no CRC.
+    const HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0xC0];
+    const FRAME_LEN: usize = 417;
+    const SAMPLES_PER_FRAME: usize = 1152;
+
+    /// A frame of digital silence: all-zero side info and main data.
+    fn silent_frame() -> Vec<u8> {
+        let mut frame = vec![0u8; FRAME_LEN];
+        frame[..HEADER_SIZE].copy_from_slice(&HEADER);
+        frame
+    }
+
+    fn frames(n: usize) -> Vec<u8> {
+        (0..n).flat_map(|_| silent_frame()).collect()
+    }
+
+    /// Output at the source format so no resampler is involved.
+    fn decoder() -> Mp3Decoder {
+        Mp3Decoder::new(44100, 1).unwrap()
+    }
+
+    #[test]
+    fn parses_frame_header() {
+        let header = FrameHeader::parse(&HEADER).unwrap();
+        assert_eq!(
+            header,
+            FrameHeader {
+                sample_rate: 44100,
+                channels: 1,
+                frame_len: FRAME_LEN,
+            }
+        );
+        assert!(FrameHeader::parse(&[0xFF, 0xFB, 0xF0, 0xC0]).is_none());
+    }
+
+    #[test]
+    fn skips_id3v2_tag() {
+        let mut data = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        data.extend_from_slice(&[0xFF; 20]);
+        data.extend(frames(2));
+
+        let pcm = decoder().decode(&data).unwrap();
+        assert_eq!(pcm.len(), 2 * SAMPLES_PER_FRAME);
+    }
+
+    #[test]
+    fn resyncs_after_garbage() {
+        let mut data = vec![0x12, 0xFF, 0x00, 0x34, 0xFF, 0xE0, 0x56];
+        data.extend(frames(2));
+
+        let mut decoder = decoder();
+        let pcm = decoder.decode(&data).unwrap();
+        assert_eq!(pcm.len(), 2 * SAMPLES_PER_FRAME);
+        assert!(decoder.pending.is_empty());
+    }
+
+    #[test]
+    fn waits_for_frame_split_across_messages() {
+        let data = frames(1);
+        let mut decoder = decoder();
+
+        assert!(decoder.decode(&data[..200]).unwrap().is_empty());
+        assert_eq!(decoder.decode(&data[200..]).unwrap().len(), SAMPLES_PER_FRAME);
+    }
+
+    #[test]
+    fn discards_garbage_without_sync_word() {
+        let mut decoder = decoder();
+        decoder.decode(&vec![0u8; MAX_PENDING_BYTES]).unwrap();
+        assert!(decoder.pending.len() < HEADER_SIZE);
+        assert_eq!(decoder.decode(&frames(1)).unwrap().len(), SAMPLES_PER_FRAME);
+    }
+
+    #[test]
+    fn caps_pending_bytes_while_waiting_for_id3v2_tag() {
+        let mut decoder = decoder();
+        // Tag claiming the maximum syncsafe size (~256 MB)
+        decoder.decode(b"ID3\x04\x00\x00\x7F\x7F\x7F\x7F").unwrap();
+        for _ in 0..3 {
+            decoder.decode(&vec![0u8; MAX_PENDING_BYTES / 2]).unwrap();
+            assert!(decoder.pending.len() <= MAX_PENDING_BYTES);
+        }
+        assert_eq!(decoder.decode(&frames(2)).unwrap().len(), 2 * SAMPLES_PER_FRAME);
+    }
+}
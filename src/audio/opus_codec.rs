@@ -4,6 +4,7 @@
 //! - Decoder: Opus decode → resample → channel convert
 
 use super::speex::Resampler;
+use super::stream_decoder::convert_channels;
 use anyhow::Result;
 
 // ======================== Opus Encoder ========================
@@ -103,7 +104,7 @@ impl OpusEncoder {
         if self.output_channels == 1 && self.input_channels > 1 {
             // Multi-channel → mono: average all channels
             let mut mono = vec![0i16; frame_size];
-            for i in 0..frame_size {
+            for (i, sample) in mono.iter_mut().enumerate() {
                 let mut sum: i32 = 0;
                 for c in 0..self.input_channels as usize {
                     let idx = i * self.input_channels as usize + c;
@@ -111,7 +112,7 @@ impl OpusEncoder {
                         sum += pcm[idx] as i32;
                     }
                 }
-                mono[i] = (sum / self.input_channels as i32) as i16;
+                *sample = (sum / self.input_channels as i32) as i16;
             }
             mono
         } else if self.output_channels == self.input_channels {
@@ -145,6 +146,7 @@ pub struct OpusDecoder {
     input_channels: u32,
     output_sample_rate: u32,
     output_channels: u32,
+    #[allow(dead_code)]
     duration_ms: u32,
 }
 
@@ -219,41 +221,12 @@ impl OpusDecoder {
         let actual_out = out_produced as usize;
 
         // Step 3: Channel conversion
-        self.convert_channels(&resampled, actual_out)
-    }
-
-    /// Convert channels from input_channels to output_channels.
-    fn convert_channels(
-        &self,
-        resampled: &[i16],
-        frame_size: usize,
-    ) -> Result<Vec<i16>> {
-        if self.output_channels == self.input_channels {
-            // Same channel count
-            Ok(resampled[..frame_size * self.output_channels as usize].to_vec())
-        } else if self.output_channels == 1 && self.input_channels > 1 {
-            // Multi-channel → mono
-            let mut mono = vec![0i16; frame_size];
-            for i in 0..frame_size {
-                let mut sum: i32 = 0;
-                for c in 0..self.input_channels as usize {
-                    sum += resampled[i * self.input_channels as usize + c] as i32;
-                }
-                mono[i] = (sum / self.input_channels as i32) as i16;
-            }
-            Ok(mono)
-        } else {
-            // Upmix / general channel mapping (e.g., mono → stereo: duplicate)
-            let mut out = vec![0i16; frame_size * self.output_channels as usize];
-            for i in 0..frame_size {
-                for c in 0..self.output_channels as usize {
-                    let src_c = c % self.input_channels as usize;
-                    out[i * self.output_channels as usize + c] =
-                        resampled[i * self.input_channels as usize + src_c];
-                }
-            }
-            Ok(out)
-        }
+        Ok(convert_channels(
+            &resampled,
+            actual_out,
+            self.input_channels,
+            self.output_channels,
+        ))
     }
 }
 
@@ -265,4 +238,10 @@ impl StreamDecoder for OpusDecoder {
     fn decode(&mut self, data: &[u8]) -> Result<Vec<i16>> {
         OpusDecoder::decode(self, data)
     }
+
+    fn reset(&mut self) -> Result<()> {
+        self.decoder.reset_state()?;
+        self.resampler.reset();
+        Ok(())
+    }
 }
@@ -0,0 +1,147 @@
+//! Raw PCM passthrough decoder (signed 16-bit, interleaved).
+//!
+//! WebSocket binary messages carry arbitrary byte counts, so a message may end
+//! in the middle of a sample or of a multi-channel frame. The incomplete tail
+//! is kept and prepended to the next message.
+
+use anyhow::Result;
+
+use super::stream_decoder::{PcmConverter, StreamDecoder};
+
+pub struct PcmDecoder {
+    converter: PcmConverter,
+    /// Bytes of an incomplete frame left over from the previous message
+    remainder: Vec<u8>,
+    input_channels: u32,
+    big_endian: bool,
+}
+
+impl PcmDecoder {
+    /// Create a new raw PCM decoder.
+    ///
+    /// * `input_sample_rate`  - Source stream sample rate (e.g. 24000)
+    /// * `input_channels`     - Source stream channels (e.g. 1)
+    /// * `big_endian`         - Source samples are big-endian s16
+    /// * `output_sample_rate` - ALSA playback sample rate
+    /// * `output_channels`    - ALSA playback channels
+    pub fn new(
+        input_sample_rate: u32,
+        input_channels: u32,
+        big_endian: bool,
+        output_sample_rate: u32,
+        output_channels: u32,
+    ) -> Result<Self> {
+        if input_channels == 0 {
+            anyhow::bail!("PCM source channel count must be at least 1");
+        }
+
+        let converter = PcmConverter::new(
+            input_sample_rate,
+            input_channels,
+            output_sample_rate,
+            output_channels,
+        )?;
+
+        Ok(Self {
+            converter,
+            remainder: Vec::new(),
+            input_channels,
+            big_endian,
+        })
+    }
+
+    /// Size of one interleaved frame in bytes.
+    fn frame_bytes(&self) -> usize {
+        2 * self.input_channels as usize
+    }
+
+    fn sample_from_bytes(&self, b: [u8; 2]) -> i16 {
+        if self.big_endian {
+            i16::from_be_bytes(b)
+        } else {
+            i16::from_le_bytes(b)
+        }
+    }
+}
+
+impl StreamDecoder for PcmDecoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<i16>> {
+        let frame_bytes = self.frame_bytes();
+
+        // Join the leftover of the previous message with the new data
+        let joined;
+        let bytes: &[u8] = if self.remainder.is_empty() {
+            data
+        } else {
+            self.remainder.extend_from_slice(data);
+            joined = std::mem::take(&mut self.remainder);
+            &joined
+        };
+
+        let complete = bytes.len() / frame_bytes * frame_bytes;
+        self.remainder.extend_from_slice(&bytes[complete..]);
+
+        if complete == 0 {
+            return Ok(Vec::new());
+        }
+
+        let samples: Vec<i16> = bytes[..complete]
+            .chunks_exact(2)
+            .map(|b| self.sample_from_bytes([b[0], b[1]]))
+            .collect();
+
+        self.converter.process(&samples)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.remainder.clear();
+        self.converter.reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_little_and_big_endian() {
+        let mut le = PcmDecoder::new(16000, 1, false, 16000, 1).unwrap();
+        assert_eq!(le.decode(&[0x34, 0x12, 0xFF, 0xFF]).unwrap(), vec![0x1234, -1]);
+
+        let mut be = PcmDecoder::new(16000, 1, true, 16000, 1).unwrap();
+        assert_eq!(be.decode(&[0x12, 0x34, 0x80, 0x00]).unwrap(), vec![0x1234, i16::MIN]);
+    }
+
+    #[test]
+    fn keeps_odd_byte_for_next_message() {
+        let mut decoder = PcmDecoder::new(16000, 1, false, 16000, 1).unwrap();
+        assert_eq!(decoder.decode(&[0x01, 0x00, 0x02]).unwrap(), vec![1]);
+        assert_eq!(decoder.decode(&[0x00, 0x03, 0x00]).unwrap(), vec![2, 3]);
+    }
+
+    #[test]
+    fn keeps_incomplete_stereo_frame() {
+        let mut decoder = PcmDecoder::new(16000, 2, false, 16000, 2).unwrap();
+        // One full frame plus the left sample of the next
+        assert_eq!(
+            decoder.decode(&[0x01, 0x00, 0x02, 0x00, 0x03, 0x00]).unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(decoder.decode(&[0x04, 0x00]).unwrap(), vec![3, 4]);
+    }
+
+    #[test]
+    fn reset_drops_remainder() {
+        let mut decoder = PcmDecoder::new(16000, 1, false, 16000, 1).unwrap();
+        assert!(decoder.decode(&[0x01]).unwrap().is_empty());
+        decoder.reset().unwrap();
+        assert_eq!(decoder.decode(&[0x05, 0x00]).unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn converts_channels() {
+        let mut decoder = PcmDecoder::new(16000, 1, false, 16000, 2).unwrap();
+        assert_eq!(decoder.decode(&[0x07, 0x00]).unwrap(), vec![7, 7]);
+    }
+}
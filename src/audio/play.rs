@@ -1,24 +1,45 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use tokio::sync::mpsc;
 use anyhow::Result;
+use alsa::pcm::PCM;
 
 use super::alsa_device;
+use super::mp3_decoder::Mp3Decoder;
 use super::opus_codec::OpusDecoder;
-use super::stream_decoder::StreamDecoder;
-use super::audio_system::AudioConfig;
+use super::pcm_decoder::PcmDecoder;
+use super::stream_decoder::{PcmConverter, StreamDecoder};
+use super::audio_system::{AudioConfig, DecoderControl, DecoderParams, PlaybackPacket};
+use super::echo_reference::EchoReference;
+use super::prompt::PcmFormat;
 
-/// Factory function: create a decoder based on the configured playback format.
+/// Factory function: create a decoder based on the configured playback format
+/// and the current stream parameters.
 fn create_decoder(
     config: &AudioConfig,
+    params: &DecoderParams,
     alsa_rate: u32,
     alsa_channels: u32,
 ) -> Result<Box<dyn StreamDecoder>> {
     match config.stream_format.as_str() {
         "opus" => {
             let decoder = OpusDecoder::new(
-                config.opus_sample_rate,
-                config.opus_channels,
-                config.decode_frame_duration_ms,
+                params.sample_rate,
+                params.channels,
+                params.frame_duration_ms,
+                alsa_rate,
+                alsa_channels,
+            )?;
+            Ok(Box::new(decoder))
+        }
+        "mp3" => {
+            let decoder = Mp3Decoder::new(alsa_rate, alsa_channels)?;
+            Ok(Box::new(decoder))
+        }
+        "pcm" => {
+            let decoder = PcmDecoder::new(
+                params.sample_rate,
+                params.channels,
+                config.pcm_big_endian,
                 alsa_rate,
                 alsa_channels,
             )?;
@@ -28,9 +49,107 @@ fn create_decoder(
     }
 }
 
+/// Decode a local voice prompt packet (little-endian s16 PCM), reusing the
+/// prompt decoder while consecutive packets share a format.
+fn decode_prompt(
+    prompt_decoder: &mut Option<(PcmFormat, PcmDecoder)>,
+    format: PcmFormat,
+    data: &[u8],
+    alsa_rate: u32,
+    alsa_channels: u32,
+) -> Result<Vec<i16>> {
+    let mut decoder = match prompt_decoder.take() {
+        Some((current, decoder)) if current == format => decoder,
+        _ => PcmDecoder::new(
+            format.sample_rate,
+            format.channels,
+            false,
+            alsa_rate,
+            alsa_channels,
+        )?,
+    };
+    let pcm = decoder.decode(data);
+    *prompt_decoder = Some((format, decoder));
+    pcm
+}
+
+/// Downmix/resample played PCM into the AEC reference at the capture rate.
+///
+/// The converter is built once the recording thread has published its
+/// negotiated rate, and rebuilt if that rate changes.
+fn feed_echo_reference(
+    reference: &EchoReference,
+    converter: &mut Option<PcmConverter>,
+    pcm_data: &[i16],
+    play_rate: u32,
+    play_channels: u32,
+) {
+    let capture_rate = reference.capture_rate();
+    if capture_rate == 0 {
+        return;
+    }
+    if converter.as_ref().is_none_or(|c| !c.matches_output(capture_rate, 1)) {
+        match PcmConverter::new(play_rate, play_channels, capture_rate, 1) {
+            Ok(c) => *converter = Some(c),
+            Err(e) => {
+                log::error!("Failed to create AEC reference converter: {}", e);
+                return;
+            }
+        }
+    }
+    if let Some(c) = converter.as_mut() {
+        match c.process(pcm_data) {
+            Ok(mono) => reference.push(&mono),
+            Err(e) => log::warn!("AEC reference conversion error: {}", e),
+        }
+    }
+}
+
+/// Abort playback after `stop_playback()`: discard what ALSA still has
+/// buffered, reset the decoder and drain stale packets from the channel.
+///
+/// Returns the first packet of the new generation if one was already queued.
+fn flush_playback(
+    pcm: &PCM,
+    decoder: &mut dyn StreamDecoder,
+    play_rx: &mut mpsc::Receiver<PlaybackPacket>,
+    generation: u64,
+    echo_reference: Option<&EchoReference>,
+) -> Option<PlaybackPacket> {
+    // drop() discards pending frames immediately; prepare() makes the PCM writable again
+    if let Err(e) = pcm.drop() {
+        log::warn!("Failed to drop PCM playback buffer: {}", e);
+    }
+    if let Err(e) = pcm.prepare() {
+        log::error!("Failed to prepare PCM playback after flush: {}", e);
+    }
+    if let Err(e) = decoder.reset() {
+        log::warn!("Failed to reset decoder: {}", e);
+    }
+    if let Some(reference) = echo_reference {
+        reference.clear();
+    }
+
+    let mut dropped = 0usize;
+    let mut next = None;
+    while let Ok(packet) = play_rx.try_recv() {
+        if packet.generation >= generation {
+            next = Some(packet);
+            break;
+        }
+        dropped += 1;
+    }
+    log::info!("Playback flushed, dropped {} queued packets", dropped);
+    next
+}
+
 pub fn play_thread(
     config: &AudioConfig,
-    mut opus_rx: mpsc::Receiver<Vec<u8>>,
+    mut play_rx: mpsc::Receiver<PlaybackPacket>,
+    playback_generation: &AtomicU64,
+    playback_timestamp: &AtomicU32,
+    decoder_control: &DecoderControl,
+    echo_reference: Option<&EchoReference>,
     running: &AtomicBool,
 ) -> Result<()> {
     // 1. Open ALSA playback device with configurable sample rate, channels, and period size
@@ -51,10 +170,15 @@ pub fn play_thread(
     let _period_size = params.period_size;
 
     // 2. Initialize decoder via factory pattern
-    let mut decoder = create_decoder(config, actual_rate, actual_channels)?;
+    let initial_params = decoder_control
+        .take_update()
+        .unwrap_or_else(|| DecoderParams::from_config(config));
+    let mut decoder = create_decoder(config, &initial_params, actual_rate, actual_channels)?;
 
     let io = pcm.io_i16()?;
 
+    let mut ref_converter: Option<PcmConverter> = None;
+
     log::info!(
         "Playback started: stream_format={}, rate={}, ch={}, period={}",
         config.stream_format,
@@ -63,15 +187,80 @@ pub fn play_thread(
         _period_size,
     );
 
+    // Built on the first local prompt packet, separate from the stream decoder
+    let mut prompt_decoder: Option<(PcmFormat, PcmDecoder)> = None;
+
+    let mut active_generation = playback_generation.load(Ordering::SeqCst);
+    // Packet already taken off the channel while flushing
+    let mut pending: Option<PlaybackPacket> = None;
+
     while running.load(Ordering::Relaxed) {
         // Block until we receive an audio packet (or channel closes)
-        match opus_rx.blocking_recv() {
-            Some(audio_data) => {
-                match decoder.decode(&audio_data) {
+        let received = match pending.take() {
+            Some(packet) => Some(packet),
+            None => play_rx.blocking_recv(),
+        };
+        match received {
+            Some(packet) => {
+                let generation = playback_generation.load(Ordering::SeqCst);
+                if generation != active_generation {
+                    active_generation = generation;
+                    prompt_decoder = None;
+                    pending = flush_playback(
+                        &pcm,
+                        decoder.as_mut(),
+                        &mut play_rx,
+                        active_generation,
+                        echo_reference,
+                    );
+                }
+                // Queued before the last stop_playback(), or an empty wake-up
+                // marker: drop without decoding
+                if packet.generation < active_generation || packet.data.is_empty() {
+                    continue;
+                }
+                // Server announced a different stream format: rebuild the decoder
+                if let Some(params) = decoder_control.take_update() {
+                    match create_decoder(config, &params, actual_rate, actual_channels) {
+                        Ok(new_decoder) => {
+                            decoder = new_decoder;
+                            log::info!(
+                                "Playback decoder reconfigured: {}Hz/{}ch/{}ms",
+                                params.sample_rate,
+                                params.channels,
+                                params.frame_duration_ms
+                            );
+                        }
+                        Err(e) => log::error!("Failed to reconfigure decoder: {}", e),
+                    }
+                }
+                let decoded = match packet.pcm_format {
+                    Some(format) => decode_prompt(
+                        &mut prompt_decoder,
+                        format,
+                        &packet.data,
+                        actual_rate,
+                        actual_channels,
+                    ),
+                    None => decoder.decode(&packet.data),
+                };
+                match decoded {
                     Ok(pcm_data) => {
                         if pcm_data.is_empty() {
                             continue;
                         }
+                        if let Some(reference) = echo_reference {
+                            feed_echo_reference(
+                                reference,
+                                &mut ref_converter,
+                                &pcm_data,
+                                actual_rate,
+                                actual_channels,
+                            );
+                        }
+                        if packet.timestamp != 0 {
+                            playback_timestamp.store(packet.timestamp, Ordering::Relaxed);
+                        }
                         // Write decoded PCM to ALSA with retry loop to handle
                         // short writes and XRUN recovery without losing frames.
                         let total_frames = pcm_data.len() / actual_channels as usize;
@@ -79,6 +268,11 @@ pub fn play_thread(
                         let mut retry_count = 0u32;
 
                         while frames_written < total_frames {
+                            // Aborted mid-packet: stop writing, the flush happens
+                            // when the wake-up marker is received
+                            if playback_generation.load(Ordering::SeqCst) != active_generation {
+                                break;
+                            }
                             let offset = frames_written * actual_channels as usize;
                             match io.writei(&pcm_data[offset..]) {
                                 Ok(n) => {
@@ -0,0 +1,206 @@
+//! Prerecorded voice prompts played locally, without the server.
+//!
+//! Used before the device is activated, when there is no TTS stream yet: the
+//! activation code is read out by concatenating a lead-in phrase with one clip
+//! per digit. Clips are 16-bit PCM WAV files (any rate/channel count) and are
+//! resampled by the playback thread.
+//!
+//! No speech is bundled with the program. build.rs generates a tone fallback
+//! (a chime and DTMF tones) that stands in for recordings missing from the
+//! prompts directory, but only when `tone_fallback` is enabled; it tells the
+//! user that a code is being read out, not which digits are being read.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::wav::{parse_wav, read_wav};
+
+macro_rules! tone {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/tone_prompt/", $name))
+    };
+}
+
+/// Lead-in phrase played before the digits, e.g. "请在手机上输入验证码".
+const ACTIVATION_CODE_CLIP: &str = "activation_code.wav";
+
+/// Tone fallback clips generated by build.rs, by file name.
+const TONE_FALLBACK_CLIPS: [(&str, &[u8]); 11] = [
+    (ACTIVATION_CODE_CLIP, tone!("activation_code.wav")),
+    ("0.wav", tone!("0.wav")),
+    ("1.wav", tone!("1.wav")),
+    ("2.wav", tone!("2.wav")),
+    ("3.wav", tone!("3.wav")),
+    ("4.wav", tone!("4.wav")),
+    ("5.wav", tone!("5.wav")),
+    ("6.wav", tone!("6.wav")),
+    ("7.wav", tone!("7.wav")),
+    ("8.wav", tone!("8.wav")),
+    ("9.wav", tone!("9.wav")),
+];
+
+/// Format of the raw PCM carried by a local prompt packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+/// One decoded clip: interleaved s16 samples.
+pub struct PromptClip {
+    pub format: PcmFormat,
+    pub samples: Vec<i16>,
+}
+
+impl PromptClip {
+    /// Load `name` from `dir`, falling back to the tone clip if the file does
+    /// not exist.
+    fn load(dir: &Path, name: &str) -> Result<Self> {
+        let path = dir.join(name);
+        let (sample_rate, channels, samples) = if path.exists() {
+            read_wav(&path)?
+        } else {
+            let (_, bytes) = TONE_FALLBACK_CLIPS
+                .iter()
+                .find(|(tone, _)| *tone == name)
+                .with_context(|| format!("{} not found", path.display()))?;
+            parse_wav(bytes, Path::new(name))?
+        };
+        Ok(Self {
+            format: PcmFormat {
+                sample_rate,
+                channels,
+            },
+            samples,
+        })
+    }
+}
+
+/// The clip set loaded from the prompts directory: `activation_code.wav`
+/// followed by `0.wav` … `9.wav`, with tone clips for missing files when the
+/// fallback is enabled.
+pub struct PromptClips {
+    activation_code: PromptClip,
+    digits: Vec<PromptClip>,
+}
+
+impl PromptClips {
+    /// Load all clips from `dir`. Fails if any clip present there is invalid,
+    /// or if any is missing and `tone_fallback` is off.
+    pub fn load(dir: &Path, tone_fallback: bool) -> Result<Self> {
+        let missing: Vec<&str> = TONE_FALLBACK_CLIPS
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| !dir.join(name).exists())
+            .collect();
+        if !missing.is_empty() {
+            if !tone_fallback {
+                anyhow::bail!(
+                    "no recording for {} in {} and tone fallback is off",
+                    missing.join(", "),
+                    dir.display()
+                );
+            }
+            log::warn!(
+                "No recording for {} in {}, playing DTMF tones instead of speech",
+                missing.join(", "),
+                dir.display()
+            );
+        }
+
+        let load = |name: &str| {
+            PromptClip::load(dir, name)
+                .with_context(|| format!("Failed to load voice prompt {}", name))
+        };
+        let activation_code = load(ACTIVATION_CODE_CLIP)?;
+        let digits = (0..10)
+            .map(|digit| load(&format!("{}.wav", digit)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            activation_code,
+            digits,
+        })
+    }
+
+    /// Clips reading out `code`: the lead-in phrase, then one clip per digit.
+    /// Characters other than ASCII digits are skipped.
+    pub fn activation_code(&self, code: &str) -> Vec<&PromptClip> {
+        std::iter::once(&self.activation_code)
+            .chain(
+                code.chars()
+                    .filter_map(|c| c.to_digit(10))
+                    .map(|digit| &self.digits[digit as usize]),
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("prompt-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn loads_clips_from_dir() {
+        let dir = temp_dir();
+        for (name, bytes) in TONE_FALLBACK_CLIPS {
+            std::fs::write(dir.join(name), bytes).unwrap();
+        }
+        let clips = PromptClips::load(&dir, false).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let format = PcmFormat { sample_rate: 16000, channels: 1 };
+        assert_eq!(clips.activation_code.format, format);
+        assert_eq!(clips.digits.len(), 10);
+        assert!(clips.digits.iter().all(|clip| clip.format == format && !clip.samples.is_empty()));
+    }
+
+    #[test]
+    fn builds_digit_sequence() {
+        let clips = PromptClips::load(Path::new("/nonexistent"), true).unwrap();
+        let sequence = clips.activation_code("80-21 3");
+
+        let expected = std::iter::once(&clips.activation_code)
+            .chain([8, 0, 2, 1, 3].map(|digit| &clips.digits[digit]));
+        assert_eq!(sequence.len(), 6);
+        assert!(sequence.iter().zip(expected).all(|(a, b)| std::ptr::eq(*a, b)));
+        // 每个数字的提示音各不相同
+        assert!(clips.digits.windows(2).all(|pair| pair[0].samples != pair[1].samples));
+    }
+
+    #[test]
+    fn files_in_dir_override_tone_clips() {
+        let dir = temp_dir();
+        let (_, seven) = TONE_FALLBACK_CLIPS[8];
+        std::fs::write(dir.join("0.wav"), seven).unwrap();
+        std::fs::write(dir.join("1.wav"), b"not a wav").unwrap();
+
+        let err = PromptClips::load(&dir, true).err().unwrap();
+        assert!(format!("{err:#}").contains("1.wav"), "{err:#}");
+        std::fs::remove_file(dir.join("1.wav")).unwrap();
+        let clips = PromptClips::load(&dir, true).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(clips.digits[0].samples, clips.digits[7].samples);
+        assert_ne!(clips.digits[1].samples, clips.digits[7].samples);
+    }
+
+    #[test]
+    fn missing_recordings_fail_without_tone_fallback() {
+        let dir = temp_dir();
+        for (name, bytes) in &TONE_FALLBACK_CLIPS[..10] {
+            std::fs::write(dir.join(name), bytes).unwrap();
+        }
+        let err = PromptClips::load(&dir, false).err().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let message = format!("{err:#}");
+        assert!(message.contains("9.wav") && !message.contains("8.wav"), "{message}");
+    }
+}
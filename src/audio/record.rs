@@ -1,15 +1,20 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use tokio::sync::mpsc;
 use anyhow::Result;
 
 use super::alsa_device;
 use super::opus_codec::OpusEncoder;
-use super::speex::Preprocessor;
-use super::audio_system::AudioConfig;
+use super::speex::{EchoCanceller, Preprocessor};
+use super::audio_system::{AudioConfig, CaptureEvent};
+use super::echo_reference::EchoReference;
+use super::vad::{EnergyDetector, SpeechSegmenter, SpeechTransition};
+use super::wake_word::WakeWordStage;
 
 pub fn record_thread(
     config: &AudioConfig,
-    opus_tx: mpsc::Sender<Vec<u8>>,
+    capture_tx: mpsc::Sender<CaptureEvent>,
+    echo_reference: Option<&EchoReference>,
+    playback_timestamp: &AtomicU32,
     running: &AtomicBool,
 ) -> Result<()> {
     // 1. Open ALSA capture device
@@ -20,14 +25,34 @@ pub fn record_thread(
     let actual_channels = params.channels;
     let period_size = params.period_size;
 
-    // 2. Initialize Speex preprocessors (one per channel for independent denoise/AGC)
+    // 2. Initialize Speex echo cancellers (one per channel, sharing the speaker reference).
+    // Declared before the preprocessors so they are dropped after them.
+    let mut echo_cancellers: Vec<EchoCanceller> = Vec::new();
+    if let Some(reference) = echo_reference {
+        reference.set_capture_rate(actual_rate);
+        let filter_length = (actual_rate * config.aec_filter_length_ms / 1000) as usize;
+        for _ in 0..actual_channels {
+            echo_cancellers.push(EchoCanceller::new(period_size, filter_length, actual_rate)?);
+        }
+    }
+    // Speaker reference for one period, and AEC output scratch buffer
+    let mut ref_frame = vec![0i16; period_size];
+    let mut aec_out = vec![0i16; period_size];
+
+    // 3. Initialize Speex preprocessors (one per channel for independent denoise/AGC)
     let mut preprocessors: Vec<Preprocessor> = Vec::new();
-    for _ in 0..actual_channels {
+    for ch in 0..actual_channels as usize {
         let mut pp = Preprocessor::new(period_size, actual_rate)?;
         pp.set_denoise(true);
         pp.set_noise_suppress(-25);
         pp.set_agc(true);
         pp.set_agc_level(24000.0);
+        pp.set_vad(true);
+        pp.set_vad_probability(35, 20);
+        // Let the preprocessor suppress the residual echo left by the canceller
+        if let Some(ec) = echo_cancellers.get(ch) {
+            pp.set_echo_state(ec);
+        }
         preprocessors.push(pp);
     }
 
@@ -35,7 +60,26 @@ pub fn record_thread(
     let mut channel_buffers: Vec<Vec<i16>> =
         (0..actual_channels).map(|_| vec![0i16; period_size]).collect();
 
-    // 3. Initialize Opus encoder (with resampling + channel conversion)
+    // Speech onset detector for barge-in (on the echo-cancelled signal, before AGC)
+    let mut barge_in_detector = config.barge_in_enabled.then(|| {
+        EnergyDetector::new(
+            config.barge_in_threshold_db,
+            config.barge_in_min_speech_ms,
+            actual_rate,
+        )
+    });
+
+    // Speech start/end from the Speex VAD (speech on any channel counts)
+    let mut speech_segmenter = SpeechSegmenter::new(config.vad_hangover_ms, actual_rate);
+
+    // Local wake-word detection on the preprocessed signal
+    let mut wake_word_stage = if config.wake_word_enabled {
+        Some(WakeWordStage::new(config, actual_rate)?)
+    } else {
+        None
+    };
+
+    // 4. Initialize Opus encoder (with resampling + channel conversion)
     let mut encoder = OpusEncoder::new(
         actual_rate,
         actual_channels,
@@ -52,21 +96,34 @@ pub fn record_thread(
 
     // ALSA read buffer (interleaved i16, one period)
     let mut read_buf = vec![0i16; period_size * actual_channels as usize];
+    // Frames already in `read_buf` from short reads. The echo canceller works
+    // on whole periods only, so with AEC enabled short reads are topped up
+    // until a full period is available.
+    let mut read_filled = 0usize;
 
     let io = pcm.io_i16()?;
 
     log::info!(
-        "Recording started: rate={}, ch={}, period={}, opus_frame_samples={}",
+        "Recording started: rate={}, ch={}, period={}, opus_frame_samples={}, aec={}, wake_word={}",
         actual_rate,
         actual_channels,
         period_size,
         input_frame_samples,
+        !echo_cancellers.is_empty(),
+        wake_word_stage.is_some(),
     );
 
     while running.load(Ordering::Relaxed) {
         // Read one period from ALSA
-        match io.readi(&mut read_buf) {
-            Ok(frames) => {
+        match io.readi(&mut read_buf[read_filled * actual_channels as usize..]) {
+            Ok(n) => {
+                read_filled += n;
+                if !echo_cancellers.is_empty() && read_filled < period_size {
+                    continue;
+                }
+                let frames = read_filled;
+                read_filled = 0;
+
                 // Split interleaved → per-channel
                 for i in 0..frames {
                     for ch in 0..actual_channels as usize {
@@ -75,9 +132,46 @@ pub fn record_thread(
                     }
                 }
 
-                // Run Speex preprocess on each channel independently
+                // Cancel the speaker echo before denoise/AGC
+                if let Some(reference) = echo_reference {
+                    reference.pop_into(&mut ref_frame);
+                    for (ch, ec) in echo_cancellers.iter_mut().enumerate() {
+                        ec.cancel(&channel_buffers[ch], &ref_frame, &mut aec_out);
+                        channel_buffers[ch].copy_from_slice(&aec_out);
+                    }
+                }
+
+                if let Some(detector) = barge_in_detector.as_mut()
+                    && detector.process(&channel_buffers, frames)
+                    && capture_tx.blocking_send(CaptureEvent::BargeIn).is_err()
+                {
+                    log::warn!("Failed to send speech event, receiver dropped");
+                    return Ok(());
+                }
+
+                // Run Speex preprocess (denoise/AGC/VAD) on each channel independently
+                let mut is_speech = false;
                 for ch in 0..actual_channels as usize {
-                    preprocessors[ch].process(&mut channel_buffers[ch][..frames]);
+                    is_speech |= preprocessors[ch].process(&mut channel_buffers[ch][..frames]);
+                }
+
+                if let Some(transition) = speech_segmenter.process(is_speech, frames) {
+                    let event = match transition {
+                        SpeechTransition::Start => CaptureEvent::SpeechStart,
+                        SpeechTransition::End => CaptureEvent::SpeechEnd,
+                    };
+                    if capture_tx.blocking_send(event).is_err() {
+                        log::warn!("Failed to send VAD event, receiver dropped");
+                        return Ok(());
+                    }
+                }
+
+                if let Some(stage) = wake_word_stage.as_mut()
+                    && let Some(word) = stage.process(&channel_buffers, frames)
+                    && capture_tx.blocking_send(CaptureEvent::WakeWord(word)).is_err()
+                {
+                    log::warn!("Failed to send wake word event, receiver dropped");
+                    return Ok(());
                 }
 
                 // Merge per-channel → interleaved
@@ -96,14 +190,15 @@ pub fn record_thread(
                 while accum_buf.len() >= input_frame_samples {
                     let frame = &accum_buf[..input_frame_samples];
                     match encoder.encode(frame) {
+                        Ok(opus_data) if opus_data.is_empty() => {}
                         Ok(opus_data) => {
-                            if !opus_data.is_empty() {
-                                if opus_tx.blocking_send(opus_data).is_err() {
-                                    log::warn!(
-                                        "Failed to send opus data, receiver dropped"
-                                    );
-                                    return Ok(());
-                                }
+                            let event = CaptureEvent::Encoded {
+                                data: opus_data,
+                                timestamp: playback_timestamp.load(Ordering::Relaxed),
+                            };
+                            if capture_tx.blocking_send(event).is_err() {
+                                log::warn!("Failed to send opus data, receiver dropped");
+                                return Ok(());
                             }
                         }
                         Err(e) => {
@@ -116,6 +211,8 @@ pub fn record_thread(
             }
             Err(e) => {
                 log::warn!("ALSA capture error: {}, recovering...", e);
+                // Samples before an overrun are not contiguous with what follows
+                read_filled = 0;
                 if let Err(e2) = pcm.prepare() {
                     log::error!("Failed to recover PCM capture: {}", e2);
                     break;
@@ -1,4 +1,5 @@
-//! Safe wrappers around SpeexDSP's preprocessor (denoise/AGC) and resampler.
+//! Safe wrappers around SpeexDSP's preprocessor (denoise/AGC), acoustic echo
+//! canceller and resampler.
 
 use std::ffi::{c_int, c_void};
 
@@ -16,11 +17,24 @@ pub struct SpeexResamplerState {
     _private: [u8; 0],
 }
 
+/// Opaque type for SpeexEchoState
+#[repr(C)]
+pub struct SpeexEchoState {
+    _private: [u8; 0],
+}
+
 // Preprocessor request constants
 const SPEEX_PREPROCESS_SET_DENOISE: c_int = 0;
 const SPEEX_PREPROCESS_SET_AGC: c_int = 2;
+const SPEEX_PREPROCESS_SET_VAD: c_int = 4;
 const SPEEX_PREPROCESS_SET_AGC_LEVEL: c_int = 6;
 const SPEEX_PREPROCESS_SET_NOISE_SUPPRESS: c_int = 8;
+const SPEEX_PREPROCESS_SET_PROB_START: c_int = 14;
+const SPEEX_PREPROCESS_SET_PROB_CONTINUE: c_int = 16;
+const SPEEX_PREPROCESS_SET_ECHO_STATE: c_int = 24;
+
+// Echo canceller request constants
+const SPEEX_ECHO_SET_SAMPLING_RATE: c_int = 24;
 
 // Resampler constants
 const SPEEX_RESAMPLER_QUALITY_DEFAULT: c_int = 4;
@@ -37,6 +51,16 @@ unsafe extern "C" {
         ptr: *mut c_void,
     ) -> c_int;
 
+    fn speex_echo_state_init(frame_size: c_int, filter_length: c_int) -> *mut SpeexEchoState;
+    fn speex_echo_state_destroy(st: *mut SpeexEchoState);
+    fn speex_echo_cancellation(
+        st: *mut SpeexEchoState,
+        rec: *const i16,
+        play: *const i16,
+        out: *mut i16,
+    );
+    fn speex_echo_ctl(st: *mut SpeexEchoState, request: c_int, ptr: *mut c_void) -> c_int;
+
     fn speex_resampler_init(
         nb_channels: u32,
         in_rate: u32,
@@ -45,6 +69,7 @@ unsafe extern "C" {
         err: *mut c_int,
     ) -> *mut SpeexResamplerState;
     fn speex_resampler_destroy(st: *mut SpeexResamplerState);
+    fn speex_resampler_reset_mem(st: *mut SpeexResamplerState) -> c_int;
     fn speex_resampler_process_int(
         st: *mut SpeexResamplerState,
         channel_index: u32,
@@ -53,6 +78,13 @@ unsafe extern "C" {
         out: *mut i16,
         out_len: *mut u32,
     ) -> c_int;
+    fn speex_resampler_process_interleaved_int(
+        st: *mut SpeexResamplerState,
+        in_: *const i16,
+        in_len: *mut u32,
+        out: *mut i16,
+        out_len: *mut u32,
+    ) -> c_int;
 }
 
 // ======================== Preprocessor (denoise + AGC) ========================
@@ -89,6 +121,39 @@ impl Preprocessor {
         }
     }
 
+    /// Enable or disable voice activity detection.
+    ///
+    /// When enabled, `process()` reports whether the frame contains speech.
+    pub fn set_vad(&mut self, enable: bool) {
+        let mut val: c_int = if enable { 1 } else { 0 };
+        unsafe {
+            speex_preprocess_ctl(
+                self.state,
+                SPEEX_PREPROCESS_SET_VAD,
+                &mut val as *mut c_int as *mut c_void,
+            );
+        }
+    }
+
+    /// Set the VAD speech probability thresholds in percent: `start` to enter
+    /// the speech state, `continue_` to stay in it (e.g. 35 / 20).
+    pub fn set_vad_probability(&mut self, start: i32, continue_: i32) {
+        let mut start: c_int = start;
+        let mut cont: c_int = continue_;
+        unsafe {
+            speex_preprocess_ctl(
+                self.state,
+                SPEEX_PREPROCESS_SET_PROB_START,
+                &mut start as *mut c_int as *mut c_void,
+            );
+            speex_preprocess_ctl(
+                self.state,
+                SPEEX_PREPROCESS_SET_PROB_CONTINUE,
+                &mut cont as *mut c_int as *mut c_void,
+            );
+        }
+    }
+
     /// Set noise suppress level in dB (negative value, e.g. -25).
     pub fn set_noise_suppress(&mut self, level: i32) {
         let mut val: c_int = level;
@@ -125,13 +190,26 @@ impl Preprocessor {
         }
     }
 
-    /// Run the preprocessor on a frame of 16-bit PCM mono samples.
-    /// The samples are modified in-place.
-    pub fn process(&mut self, samples: &mut [i16]) {
+    /// Attach an echo canceller so the preprocessor also suppresses residual echo.
+    ///
+    /// The echo canceller must outlive every subsequent call to `process()`.
+    pub fn set_echo_state(&mut self, echo: &EchoCanceller) {
         unsafe {
-            speex_preprocess_run(self.state, samples.as_mut_ptr());
+            speex_preprocess_ctl(
+                self.state,
+                SPEEX_PREPROCESS_SET_ECHO_STATE,
+                echo.state as *mut c_void,
+            );
         }
     }
+
+    /// Run the preprocessor on a frame of 16-bit PCM mono samples.
+    /// The samples are modified in-place.
+    ///
+    /// Returns `true` if VAD is enabled and the frame contains speech.
+    pub fn process(&mut self, samples: &mut [i16]) -> bool {
+        unsafe { speex_preprocess_run(self.state, samples.as_mut_ptr()) != 0 }
+    }
 }
 
 impl Drop for Preprocessor {
@@ -142,11 +220,70 @@ impl Drop for Preprocessor {
     }
 }
 
+// ======================== Echo canceller (AEC) ========================
+
+/// Safe wrapper around SpeexEchoState (MDF acoustic echo canceller).
+pub struct EchoCanceller {
+    state: *mut SpeexEchoState,
+    frame_size: usize,
+}
+
+// SpeexEchoState is used from a single thread only
+unsafe impl Send for EchoCanceller {}
+
+impl EchoCanceller {
+    /// Create a new echo canceller.
+    ///
+    /// * `frame_size`    - Samples processed per call (one capture period)
+    /// * `filter_length` - Echo tail length in samples (e.g. 200ms worth)
+    /// * `sample_rate`   - Sample rate of both the capture and reference signals
+    pub fn new(frame_size: usize, filter_length: usize, sample_rate: u32) -> anyhow::Result<Self> {
+        let state =
+            unsafe { speex_echo_state_init(frame_size as c_int, filter_length as c_int) };
+        if state.is_null() {
+            anyhow::bail!("Failed to initialize speex echo canceller");
+        }
+        let mut rate: c_int = sample_rate as c_int;
+        unsafe {
+            speex_echo_ctl(
+                state,
+                SPEEX_ECHO_SET_SAMPLING_RATE,
+                &mut rate as *mut c_int as *mut c_void,
+            );
+        }
+        Ok(Self { state, frame_size })
+    }
+
+    /// Remove the echo of `play` (speaker reference) from `rec` (microphone).
+    ///
+    /// All slices must hold at least `frame_size` mono samples.
+    pub fn cancel(&mut self, rec: &[i16], play: &[i16], out: &mut [i16]) {
+        assert!(
+            rec.len() >= self.frame_size
+                && play.len() >= self.frame_size
+                && out.len() >= self.frame_size,
+            "echo canceller buffers shorter than frame size"
+        );
+        unsafe {
+            speex_echo_cancellation(self.state, rec.as_ptr(), play.as_ptr(), out.as_mut_ptr());
+        }
+    }
+}
+
+impl Drop for EchoCanceller {
+    fn drop(&mut self) {
+        unsafe {
+            speex_echo_state_destroy(self.state);
+        }
+    }
+}
+
 // ======================== Resampler ========================
 
 /// Safe wrapper around SpeexResamplerState.
 pub struct Resampler {
     state: *mut SpeexResamplerState,
+    channels: u32,
 }
 
 unsafe impl Send for Resampler {}
@@ -171,7 +308,7 @@ impl Resampler {
         if err != RESAMPLER_ERR_SUCCESS || state.is_null() {
             anyhow::bail!("Failed to initialize speex resampler: err={}", err);
         }
-        Ok(Self { state })
+        Ok(Self { state, channels })
     }
 
     /// Resample a single channel of 16-bit PCM data.
@@ -200,6 +337,39 @@ impl Resampler {
         }
         Ok((in_len, out_len))
     }
+
+    /// Resample interleaved 16-bit PCM data covering all channels.
+    ///
+    /// Lengths are in interleaved samples; returns
+    /// `(input_frames_consumed, output_frames_produced)` per channel.
+    pub fn process_interleaved_int(
+        &mut self,
+        input: &[i16],
+        output: &mut [i16],
+    ) -> anyhow::Result<(u32, u32)> {
+        let mut in_len = (input.len() / self.channels as usize) as u32;
+        let mut out_len = (output.len() / self.channels as usize) as u32;
+        let err = unsafe {
+            speex_resampler_process_interleaved_int(
+                self.state,
+                input.as_ptr(),
+                &mut in_len,
+                output.as_mut_ptr(),
+                &mut out_len,
+            )
+        };
+        if err != RESAMPLER_ERR_SUCCESS {
+            anyhow::bail!("Speex resampler error: {}", err);
+        }
+        Ok((in_len, out_len))
+    }
+
+    /// Clear the filter history so the next block starts from silence.
+    pub fn reset(&mut self) {
+        unsafe {
+            speex_resampler_reset_mem(self.state);
+        }
+    }
 }
 
 impl Drop for Resampler {
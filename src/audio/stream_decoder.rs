@@ -2,6 +2,8 @@
 
 use anyhow::Result;
 
+use super::speex::Resampler;
+
 /// A trait for audio stream decoders that convert compressed audio data
 /// into interleaved i16 PCM samples ready for ALSA playback.
 ///
@@ -10,4 +12,146 @@ use anyhow::Result;
 pub trait StreamDecoder: Send {
     /// Decode compressed audio bytes into interleaved i16 PCM samples.
     fn decode(&mut self, data: &[u8]) -> Result<Vec<i16>>;
+
+    /// Discard buffered input and codec/resampler history, e.g. after
+    /// playback was aborted, so the next stream starts cleanly.
+    fn reset(&mut self) -> Result<()>;
+}
+
+/// Convert interleaved PCM from `input_channels` to `output_channels`.
+///
+/// * Same channel count: passthrough
+/// * Multi-channel → mono: average all channels
+/// * Otherwise: wrap channels (e.g. mono → stereo duplicates the sample)
+pub fn convert_channels(
+    pcm: &[i16],
+    frame_size: usize,
+    input_channels: u32,
+    output_channels: u32,
+) -> Vec<i16> {
+    let in_ch = input_channels as usize;
+    let out_ch = output_channels as usize;
+
+    if out_ch == in_ch {
+        pcm[..frame_size * out_ch].to_vec()
+    } else if out_ch == 1 && in_ch > 1 {
+        let mut mono = vec![0i16; frame_size];
+        for (i, sample) in mono.iter_mut().enumerate() {
+            let sum: i32 = pcm[i * in_ch..(i + 1) * in_ch]
+                .iter()
+                .map(|&s| s as i32)
+                .sum();
+            *sample = (sum / in_ch as i32) as i16;
+        }
+        mono
+    } else {
+        let mut out = vec![0i16; frame_size * out_ch];
+        for i in 0..frame_size {
+            for c in 0..out_ch {
+                out[i * out_ch + c] = pcm[i * in_ch + c % in_ch];
+            }
+        }
+        out
+    }
+}
+
+/// Resampling + channel conversion stage shared by PCM-producing decoders
+/// and the AEC reference path.
+///
+/// Converts interleaved PCM at the source format into the negotiated ALSA
+/// playback format. The resampler is skipped when the rates already match.
+pub struct PcmConverter {
+    resampler: Option<Resampler>,
+    input_sample_rate: u32,
+    input_channels: u32,
+    output_sample_rate: u32,
+    output_channels: u32,
+}
+
+impl PcmConverter {
+    /// * `input_sample_rate`  - Source stream sample rate
+    /// * `input_channels`     - Source stream channels
+    /// * `output_sample_rate` - ALSA playback sample rate
+    /// * `output_channels`    - ALSA playback channels
+    pub fn new(
+        input_sample_rate: u32,
+        input_channels: u32,
+        output_sample_rate: u32,
+        output_channels: u32,
+    ) -> Result<Self> {
+        let resampler = if input_sample_rate != output_sample_rate {
+            Some(Resampler::new(
+                input_channels,
+                input_sample_rate,
+                output_sample_rate,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            resampler,
+            input_sample_rate,
+            input_channels,
+            output_sample_rate,
+            output_channels,
+        })
+    }
+
+    /// Whether this converter was built for the given source format.
+    pub fn matches_input(&self, sample_rate: u32, channels: u32) -> bool {
+        self.input_sample_rate == sample_rate && self.input_channels == channels
+    }
+
+    /// Whether this converter produces the given output format.
+    pub fn matches_output(&self, sample_rate: u32, channels: u32) -> bool {
+        self.output_sample_rate == sample_rate && self.output_channels == channels
+    }
+
+    /// Clear the resampler history.
+    pub fn reset(&mut self) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+        }
+    }
+
+    /// Convert a block of interleaved source PCM to the playback format.
+    pub fn process(&mut self, pcm: &[i16]) -> Result<Vec<i16>> {
+        let in_frames = pcm.len() / self.input_channels as usize;
+
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(convert_channels(
+                pcm,
+                in_frames,
+                self.input_channels,
+                self.output_channels,
+            ));
+        };
+
+        let expected_out_frames = (in_frames as f64
+            * (self.output_sample_rate as f64 / self.input_sample_rate as f64))
+            .ceil() as usize;
+        // Allocate slightly larger to handle rounding
+        let mut resampled = vec![0i16; (expected_out_frames + 64) * self.input_channels as usize];
+
+        let (in_consumed, out_produced) = resampler.process_interleaved_int(
+            &pcm[..in_frames * self.input_channels as usize],
+            &mut resampled,
+        )?;
+
+        if in_consumed as usize != in_frames {
+            log::warn!(
+                "Resampler did not consume all input: consumed={}, total={}",
+                in_consumed,
+                in_frames
+            );
+        }
+
+        Ok(convert_channels(
+            &resampled,
+            out_produced as usize,
+            self.input_channels,
+            self.output_channels,
+        ))
+    }
 }
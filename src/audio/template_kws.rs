@@ -0,0 +1,497 @@
+//! Built-in template-matching keyword spotter (MFCC + DTW).
+//!
+//! The wake word is enrolled from a few short WAV recordings of the phrase
+//! (16-bit PCM, any rate/channel count). Live audio is converted to MFCC
+//! frames; every few frames the most recent window is aligned against each
+//! template with dynamic time warping, and the detector fires when the
+//! normalised alignment cost drops below the configured threshold.
+//!
+//! Both sides use cepstral mean normalisation over the compared window, so the
+//! match is largely independent of microphone gain and channel colouring.
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::stream_decoder::PcmConverter;
+use super::wav::read_wav;
+use super::wake_word::WakeWordDetector;
+
+/// Analysis sample rate.
+const SAMPLE_RATE: u32 = 16000;
+/// 25 ms analysis window.
+const FRAME_LEN: usize = 400;
+/// 10 ms hop between frames.
+const HOP_LEN: usize = 160;
+const FFT_LEN: usize = 512;
+const NUM_FILTERS: usize = 26;
+/// Cepstral coefficients c1..c12 (c0 is dropped, energy is tracked separately).
+const NUM_CEPS: usize = 12;
+const PRE_EMPHASIS: f32 = 0.97;
+/// Frames between two DTW evaluations (50 ms).
+const EVAL_INTERVAL: usize = 5;
+/// A window is only matched if at least one frame is louder than this (dBFS).
+const MIN_SPEECH_DB: f32 = -45.0;
+/// Template frames quieter than the peak by this much are trimmed from both ends.
+const TRIM_DB: f32 = 30.0;
+/// Shortest usable template after trimming (frames).
+const MIN_TEMPLATE_FRAMES: usize = 20;
+
+type Feature = [f32; NUM_CEPS];
+
+// ======================== FFT ========================
+
+/// Radix-2 complex FFT of fixed size, used for the power spectrum.
+struct Fft {
+    n: usize,
+    cos: Vec<f32>,
+    sin: Vec<f32>,
+    bit_reverse: Vec<usize>,
+}
+
+impl Fft {
+    fn new(n: usize) -> Self {
+        let bits = n.trailing_zeros();
+        let bit_reverse = (0..n)
+            .map(|i| i.reverse_bits() >> (usize::BITS - bits))
+            .collect();
+        let (cos, sin) = (0..n / 2)
+            .map(|k| {
+                let angle = 2.0 * PI * k as f32 / n as f32;
+                (angle.cos(), angle.sin())
+            })
+            .unzip();
+        Self {
+            n,
+            cos,
+            sin,
+            bit_reverse,
+        }
+    }
+
+    /// Power spectrum of a real signal (zero-padded to `n`), bins `0..=n/2`.
+    fn power_spectrum(&self, input: &[f32], power: &mut [f32]) {
+        let mut re = vec![0f32; self.n];
+        let mut im = vec![0f32; self.n];
+        for (i, &x) in input.iter().enumerate().take(self.n) {
+            re[self.bit_reverse[i]] = x;
+        }
+
+        let mut size = 2;
+        while size <= self.n {
+            let half = size / 2;
+            let step = self.n / size;
+            for start in (0..self.n).step_by(size) {
+                for k in 0..half {
+                    let (wr, wi) = (self.cos[k * step], -self.sin[k * step]);
+                    let a = start + k;
+                    let b = a + half;
+                    let tr = re[b] * wr - im[b] * wi;
+                    let ti = re[b] * wi + im[b] * wr;
+                    re[b] = re[a] - tr;
+                    im[b] = im[a] - ti;
+                    re[a] += tr;
+                    im[a] += ti;
+                }
+            }
+            size *= 2;
+        }
+
+        for (k, p) in power.iter_mut().enumerate() {
+            *p = re[k] * re[k] + im[k] * im[k];
+        }
+    }
+}
+
+// ======================== MFCC ========================
+
+/// Streaming MFCC front end: 16 kHz mono i16 in, one feature per 10 ms out.
+struct FeatureExtractor {
+    fft: Fft,
+    window: Vec<f32>,
+    /// Triangular mel filters over FFT bins `0..=FFT_LEN/2`
+    filters: Vec<Vec<f32>>,
+    /// DCT-II basis, `NUM_CEPS` rows of `NUM_FILTERS`
+    dct: Vec<Vec<f32>>,
+    /// Pre-emphasised samples not yet consumed by a full frame
+    samples: Vec<f32>,
+    prev_sample: f32,
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+impl FeatureExtractor {
+    fn new() -> Self {
+        let window = (0..FRAME_LEN)
+            .map(|i| 0.54 - 0.46 * (2.0 * PI * i as f32 / (FRAME_LEN - 1) as f32).cos())
+            .collect();
+
+        let bins = FFT_LEN / 2 + 1;
+        let low = hz_to_mel(20.0);
+        let high = hz_to_mel(SAMPLE_RATE as f32 / 2.0);
+        let centers: Vec<f32> = (0..NUM_FILTERS + 2)
+            .map(|i| {
+                let mel = low + (high - low) * i as f32 / (NUM_FILTERS + 1) as f32;
+                mel_to_hz(mel) * FFT_LEN as f32 / SAMPLE_RATE as f32
+            })
+            .collect();
+        let filters = (0..NUM_FILTERS)
+            .map(|m| {
+                let (left, center, right) = (centers[m], centers[m + 1], centers[m + 2]);
+                (0..bins)
+                    .map(|k| {
+                        let k = k as f32;
+                        if k > left && k <= center {
+                            (k - left) / (center - left)
+                        } else if k > center && k < right {
+                            (right - k) / (right - center)
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let dct = (1..=NUM_CEPS)
+            .map(|c| {
+                (0..NUM_FILTERS)
+                    .map(|m| (PI * c as f32 * (m as f32 + 0.5) / NUM_FILTERS as f32).cos())
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            fft: Fft::new(FFT_LEN),
+            window,
+            filters,
+            dct,
+            samples: Vec::with_capacity(FRAME_LEN * 2),
+            prev_sample: 0.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.samples.clear();
+        self.prev_sample = 0.0;
+    }
+
+    /// Append PCM and emit `(cepstra, frame level in dBFS)` for every complete frame.
+    fn push(&mut self, pcm: &[i16], out: &mut Vec<(Feature, f32)>) {
+        for &s in pcm {
+            let x = s as f32 / 32768.0;
+            self.samples.push(x - PRE_EMPHASIS * self.prev_sample);
+            self.prev_sample = x;
+        }
+
+        let mut offset = 0;
+        while offset + FRAME_LEN <= self.samples.len() {
+            out.push(self.compute(&self.samples[offset..offset + FRAME_LEN]));
+            offset += HOP_LEN;
+        }
+        self.samples.drain(..offset);
+    }
+
+    fn compute(&self, frame: &[f32]) -> (Feature, f32) {
+        let mean_square = frame.iter().map(|x| x * x).sum::<f32>() / frame.len() as f32;
+        let level_db = 10.0 * mean_square.max(1e-10).log10();
+
+        let windowed: Vec<f32> = frame.iter().zip(&self.window).map(|(x, w)| x * w).collect();
+        let mut power = vec![0f32; FFT_LEN / 2 + 1];
+        self.fft.power_spectrum(&windowed, &mut power);
+
+        let log_energies: Vec<f32> = self
+            .filters
+            .iter()
+            .map(|filter| {
+                let e: f32 = filter.iter().zip(&power).map(|(w, p)| w * p).sum();
+                e.max(1e-10).ln()
+            })
+            .collect();
+
+        let mut ceps = [0f32; NUM_CEPS];
+        for (c, basis) in ceps.iter_mut().zip(&self.dct) {
+            *c = basis.iter().zip(&log_energies).map(|(b, e)| b * e).sum();
+        }
+        (ceps, level_db)
+    }
+}
+
+/// Subtract the per-coefficient mean over a sequence of frames.
+fn mean_normalize<'a>(frames: impl Iterator<Item = &'a Feature> + Clone) -> Vec<Feature> {
+    let count = frames.clone().count().max(1) as f32;
+    let mut mean = [0f32; NUM_CEPS];
+    for f in frames.clone() {
+        for (m, v) in mean.iter_mut().zip(f) {
+            *m += v;
+        }
+    }
+    mean.iter_mut().for_each(|m| *m /= count);
+
+    frames
+        .map(|f| {
+            let mut out = *f;
+            out.iter_mut().zip(&mean).for_each(|(v, m)| *v -= m);
+            out
+        })
+        .collect()
+}
+
+fn distance(a: &Feature, b: &Feature) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// DTW alignment cost between two sequences, normalised by `n + m`.
+/// A Sakoe-Chiba band limits warping to a quarter of the length.
+fn dtw_cost(a: &[Feature], b: &[Feature]) -> f32 {
+    let (n, m) = (a.len(), b.len());
+    let band = (n.max(m) / 4).max(n.abs_diff(m)) + 1;
+    let mut prev = vec![f32::INFINITY; m + 1];
+    let mut curr = vec![f32::INFINITY; m + 1];
+    prev[0] = 0.0;
+
+    for i in 1..=n {
+        curr.fill(f32::INFINITY);
+        let center = i * m / n;
+        let lo = center.saturating_sub(band).max(1);
+        let hi = (center + band).min(m);
+        for j in lo..=hi {
+            let best = prev[j].min(curr[j - 1]).min(prev[j - 1]);
+            curr[j] = distance(&a[i - 1], &b[j - 1]) + best;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[m] / (n + m) as f32
+}
+
+// ======================== Template loading ========================
+
+/// Load a recording of the wake word as a mean-normalised feature sequence.
+fn load_template(path: &Path) -> Result<Vec<Feature>> {
+    let (rate, channels, samples) = read_wav(path)?;
+    let mut converter = PcmConverter::new(rate, channels, SAMPLE_RATE, 1)?;
+    let pcm = converter.process(&samples)?;
+
+    let mut extractor = FeatureExtractor::new();
+    let mut frames = Vec::new();
+    extractor.push(&pcm, &mut frames);
+
+    // Trim leading/trailing silence relative to the loudest frame
+    let peak = frames
+        .iter()
+        .map(|(_, db)| *db)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let loud = |(_, db): &(Feature, f32)| *db >= peak - TRIM_DB;
+    let start = frames.iter().position(loud).unwrap_or(0);
+    let end = frames.iter().rposition(loud).map_or(0, |i| i + 1);
+    let trimmed = &frames[start..end.max(start)];
+
+    if trimmed.len() < MIN_TEMPLATE_FRAMES {
+        anyhow::bail!(
+            "{}: wake word template too short ({} ms of speech)",
+            path.display(),
+            trimmed.len() * 10
+        );
+    }
+    Ok(mean_normalize(trimmed.iter().map(|(f, _)| f)))
+}
+
+// ======================== Detector ========================
+
+pub struct TemplateDetector {
+    wake_word: String,
+    templates: Vec<Vec<Feature>>,
+    /// Maximum normalised DTW cost accepted as a detection
+    threshold: f32,
+    extractor: FeatureExtractor,
+    /// Most recent frames, as long as the longest template
+    history: VecDeque<(Feature, f32)>,
+    max_len: usize,
+    frames_since_eval: usize,
+    new_frames: Vec<(Feature, f32)>,
+}
+
+impl TemplateDetector {
+    /// * `wake_word`      - Text reported on detection (e.g. "你好小智")
+    /// * `template_paths` - WAV recordings of the wake word
+    /// * `threshold`      - Maximum normalised DTW cost for a match
+    pub fn new(wake_word: &str, template_paths: &[String], threshold: f32) -> Result<Self> {
+        let templates = template_paths
+            .iter()
+            .map(|p| load_template(Path::new(p)))
+            .collect::<Result<Vec<_>>>()?;
+        if templates.is_empty() {
+            anyhow::bail!("No wake word templates configured");
+        }
+        let max_len = templates.iter().map(Vec::len).max().unwrap_or(0);
+
+        log::info!(
+            "Wake word \"{}\" enrolled from {} template(s), threshold={}",
+            wake_word,
+            templates.len(),
+            threshold
+        );
+
+        Ok(Self {
+            wake_word: wake_word.to_string(),
+            templates,
+            threshold,
+            extractor: FeatureExtractor::new(),
+            history: VecDeque::with_capacity(max_len),
+            max_len,
+            frames_since_eval: 0,
+            new_frames: Vec::new(),
+        })
+    }
+
+    /// Lowest DTW cost of the current history against any template,
+    /// or `None` if no window is ready or the window contains no speech.
+    fn best_cost(&self) -> Option<f32> {
+        self.templates
+            .iter()
+            .filter(|t| t.len() <= self.history.len())
+            .filter_map(|template| {
+                let window = self.history.range(self.history.len() - template.len()..);
+                if !window.clone().any(|(_, db)| *db >= MIN_SPEECH_DB) {
+                    return None;
+                }
+                let live = mean_normalize(window.map(|(f, _)| f));
+                Some(dtw_cost(&live, template))
+            })
+            .reduce(f32::min)
+    }
+}
+
+impl WakeWordDetector for TemplateDetector {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn process(&mut self, pcm: &[i16]) -> Option<String> {
+        self.new_frames.clear();
+        self.extractor.push(pcm, &mut self.new_frames);
+
+        for frame in self.new_frames.drain(..) {
+            if self.history.len() == self.max_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(frame);
+            self.frames_since_eval += 1;
+        }
+
+        if self.frames_since_eval < EVAL_INTERVAL {
+            return None;
+        }
+        self.frames_since_eval = 0;
+
+        let cost = self.best_cost()?;
+        log::trace!("Wake word DTW cost {:.3}", cost);
+        if cost <= self.threshold {
+            log::info!("Wake word \"{}\" detected (cost {:.3})", self.wake_word, cost);
+            return Some(self.wake_word.clone());
+        }
+        None
+    }
+
+    fn reset(&mut self) {
+        self.extractor.reset();
+        self.history.clear();
+        self.frames_since_eval = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Default `wake_word.threshold` from config.toml.
+    const DEFAULT_THRESHOLD: f32 = 5.0;
+
+    /// Tone sweeping linearly from `f0` to `f1` Hz over `secs` at 16 kHz.
+    fn sweep(f0: f32, f1: f32, secs: f32) -> Vec<i16> {
+        let n = (secs * SAMPLE_RATE as f32) as usize;
+        let mut phase = 0f32;
+        (0..n)
+            .map(|i| {
+                let f = f0 + (f1 - f0) * i as f32 / n as f32;
+                phase += 2.0 * PI * f / SAMPLE_RATE as f32;
+                (phase.sin() * 12000.0) as i16
+            })
+            .collect()
+    }
+
+    /// Deterministic white noise.
+    fn noise(secs: f32) -> Vec<i16> {
+        let mut state = 0x1234_5678u32;
+        (0..(secs * SAMPLE_RATE as f32) as usize)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 16) as i16 / 3
+            })
+            .collect()
+    }
+
+    fn features(pcm: &[i16]) -> Vec<Feature> {
+        let mut frames = Vec::new();
+        FeatureExtractor::new().push(pcm, &mut frames);
+        mean_normalize(frames.iter().map(|(f, _)| f))
+    }
+
+    #[test]
+    fn fft_peaks_at_sinusoid_bin() {
+        let fft = Fft::new(FFT_LEN);
+        let bin = 32;
+        let input: Vec<f32> = (0..FFT_LEN)
+            .map(|i| (2.0 * PI * bin as f32 * i as f32 / FFT_LEN as f32).cos())
+            .collect();
+        let mut power = vec![0f32; FFT_LEN / 2 + 1];
+        fft.power_spectrum(&input, &mut power);
+
+        // A unit cosine on an exact bin has magnitude N/2 there and nothing elsewhere
+        let expected = (FFT_LEN as f32 / 2.0).powi(2);
+        assert!((power[bin] - expected).abs() / expected < 1e-3);
+        for (k, p) in power.iter().enumerate() {
+            if k != bin {
+                assert!(*p < expected * 1e-6, "bin {} has power {}", k, p);
+            }
+        }
+    }
+
+    #[test]
+    fn extracts_one_feature_per_hop() {
+        let mut frames = Vec::new();
+        let mut extractor = FeatureExtractor::new();
+        extractor.push(&vec![0i16; FRAME_LEN + 9 * HOP_LEN], &mut frames);
+        assert_eq!(frames.len(), 10);
+    }
+
+    #[test]
+    fn identical_templates_cost_nothing() {
+        let template = features(&sweep(300.0, 1500.0, 0.6));
+        assert!(dtw_cost(&template, &template) < 1e-4);
+    }
+
+    #[test]
+    fn mismatched_signals_exceed_threshold() {
+        let template = features(&sweep(300.0, 1500.0, 0.6));
+        let other = features(&noise(0.6));
+        let cost = dtw_cost(&template, &other);
+        assert!(cost > DEFAULT_THRESHOLD, "cost {}", cost);
+    }
+
+    #[test]
+    fn time_stretched_match_is_cheaper_than_mismatch() {
+        let template = features(&sweep(300.0, 1500.0, 0.6));
+        let slower = features(&sweep(300.0, 1500.0, 0.7));
+        let other = features(&noise(0.6));
+        assert!(dtw_cost(&template, &slower) < dtw_cost(&template, &other));
+    }
+}
@@ -0,0 +1,217 @@
+//! Voice activity helpers for the recording thread.
+//!
+//! * [`EnergyDetector`]: lightweight energy-based speech onset detection used
+//!   for barge-in. Runs on the echo-cancelled signal before AGC, so the
+//!   measured level reflects the talker and not the gain stage.
+//! * [`SpeechSegmenter`]: turns per-frame Speex VAD decisions into speech
+//!   start/end transitions with a hangover.
+
+/// Detects sustained speech onsets by comparing frame energy to a threshold.
+///
+/// Fires once when the level stays above the threshold for `min_speech`
+/// samples, then re-arms after the level stays below it for the same time.
+pub struct EnergyDetector {
+    /// Mean-square threshold (linear, on i16 scale)
+    threshold: f64,
+    /// Number of samples (per channel) required to confirm speech / silence
+    min_run: usize,
+    active_run: usize,
+    quiet_run: usize,
+    triggered: bool,
+}
+
+impl EnergyDetector {
+    /// * `threshold_db`   - Level threshold in dBFS (e.g. -35)
+    /// * `min_speech_ms`  - Minimum duration above the threshold
+    /// * `sample_rate`    - Sample rate of the analysed signal
+    pub fn new(threshold_db: i32, min_speech_ms: u32, sample_rate: u32) -> Self {
+        let full_scale = i16::MAX as f64;
+        let amplitude = full_scale * 10f64.powf(threshold_db as f64 / 20.0);
+        Self {
+            threshold: amplitude * amplitude,
+            min_run: (sample_rate as usize * min_speech_ms as usize / 1000).max(1),
+            active_run: 0,
+            quiet_run: 0,
+            triggered: false,
+        }
+    }
+
+    /// Feed one block of per-channel buffers (`frames` samples each).
+    /// Returns `true` exactly once per detected speech onset.
+    pub fn process(&mut self, channels: &[Vec<i16>], frames: usize) -> bool {
+        if channels.is_empty() || frames == 0 {
+            return false;
+        }
+
+        let sum: f64 = channels
+            .iter()
+            .flat_map(|ch| ch[..frames].iter())
+            .map(|&s| (s as f64) * (s as f64))
+            .sum();
+        let mean_square = sum / (frames * channels.len()) as f64;
+
+        if mean_square >= self.threshold {
+            self.active_run += frames;
+            self.quiet_run = 0;
+        } else {
+            self.quiet_run += frames;
+            self.active_run = 0;
+        }
+
+        if !self.triggered && self.active_run >= self.min_run {
+            self.triggered = true;
+            return true;
+        }
+        if self.triggered && self.quiet_run >= self.min_run {
+            self.triggered = false;
+        }
+        false
+    }
+}
+
+/// A speech state transition reported by [`SpeechSegmenter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeechTransition {
+    Start,
+    End,
+}
+
+/// Tracks per-frame VAD decisions and reports speech start immediately and
+/// speech end once the VAD has stayed silent for the hangover time, so short
+/// pauses between words do not split an utterance.
+pub struct SpeechSegmenter {
+    /// Number of silent samples (per channel) that end an utterance
+    hangover: usize,
+    silent_run: usize,
+    speaking: bool,
+}
+
+impl SpeechSegmenter {
+    /// * `hangover_ms` - Silence after speech before reporting its end
+    /// * `sample_rate` - Sample rate of the analysed signal
+    pub fn new(hangover_ms: u32, sample_rate: u32) -> Self {
+        Self {
+            hangover: (sample_rate as usize * hangover_ms as usize / 1000).max(1),
+            silent_run: 0,
+            speaking: false,
+        }
+    }
+
+    /// Feed the VAD decision for a block of `frames` samples.
+    pub fn process(&mut self, is_speech: bool, frames: usize) -> Option<SpeechTransition> {
+        if is_speech {
+            self.silent_run = 0;
+            if !self.speaking {
+                self.speaking = true;
+                return Some(SpeechTransition::Start);
+            }
+        } else if self.speaking {
+            self.silent_run += frames;
+            if self.silent_run >= self.hangover {
+                self.speaking = false;
+                self.silent_run = 0;
+                return Some(SpeechTransition::End);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 samples per block at 1 kHz, i.e. 10 ms blocks.
+    const BLOCK: usize = 10;
+
+    fn block(level: i16) -> Vec<Vec<i16>> {
+        vec![vec![level; BLOCK]]
+    }
+
+    /// Feed `count` blocks at `level`, returning the indices that fired.
+    fn feed(detector: &mut EnergyDetector, level: i16, count: usize) -> Vec<usize> {
+        (0..count)
+            .filter(|_| detector.process(&block(level), BLOCK))
+            .collect()
+    }
+
+    #[test]
+    fn energy_fires_after_min_speech_above_threshold() {
+        // -20 dBFS is an amplitude of ~3277; 30 ms = 3 blocks
+        let mut detector = EnergyDetector::new(-20, 30, 1000);
+        assert!(feed(&mut detector, 3000, 10).is_empty());
+        assert_eq!(feed(&mut detector, 4000, 5), [2]);
+    }
+
+    #[test]
+    fn energy_short_bursts_do_not_fire() {
+        let mut detector = EnergyDetector::new(-20, 30, 1000);
+        for _ in 0..5 {
+            assert!(feed(&mut detector, 10000, 2).is_empty());
+            assert!(feed(&mut detector, 0, 1).is_empty());
+        }
+    }
+
+    #[test]
+    fn energy_rearms_only_after_sustained_silence() {
+        let mut detector = EnergyDetector::new(-20, 30, 1000);
+        assert_eq!(feed(&mut detector, 10000, 3), [2]);
+        // Silence shorter than min_speech keeps it triggered
+        assert!(feed(&mut detector, 0, 2).is_empty());
+        assert!(feed(&mut detector, 10000, 5).is_empty());
+        assert!(feed(&mut detector, 0, 3).is_empty());
+        assert_eq!(feed(&mut detector, 10000, 3), [2]);
+    }
+
+    #[test]
+    fn energy_averages_over_channels() {
+        let mut detector = EnergyDetector::new(-20, 10, 1000);
+        let loud_and_silent = vec![vec![10000; BLOCK], vec![0; BLOCK]];
+        assert!(detector.process(&loud_and_silent, BLOCK));
+        assert!(!detector.process(&[], BLOCK));
+    }
+
+    #[test]
+    fn segmenter_reports_start_immediately() {
+        let mut segmenter = SpeechSegmenter::new(50, 1000);
+        assert_eq!(segmenter.process(false, BLOCK), None);
+        assert_eq!(segmenter.process(true, BLOCK), Some(SpeechTransition::Start));
+        assert_eq!(segmenter.process(true, BLOCK), None);
+    }
+
+    #[test]
+    fn segmenter_ends_after_hangover() {
+        let mut segmenter = SpeechSegmenter::new(50, 1000);
+        segmenter.process(true, BLOCK);
+        for _ in 0..4 {
+            assert_eq!(segmenter.process(false, BLOCK), None);
+        }
+        assert_eq!(segmenter.process(false, BLOCK), Some(SpeechTransition::End));
+        assert_eq!(segmenter.process(false, BLOCK), None);
+    }
+
+    #[test]
+    fn segmenter_pause_shorter_than_hangover_continues_utterance() {
+        let mut segmenter = SpeechSegmenter::new(50, 1000);
+        segmenter.process(true, BLOCK);
+        for _ in 0..4 {
+            assert_eq!(segmenter.process(false, BLOCK), None);
+        }
+        // Speech resumes: no new Start, and the hangover starts over
+        assert_eq!(segmenter.process(true, BLOCK), None);
+        for _ in 0..4 {
+            assert_eq!(segmenter.process(false, BLOCK), None);
+        }
+        assert_eq!(segmenter.process(false, BLOCK), Some(SpeechTransition::End));
+    }
+
+    #[test]
+    fn segmenter_rearms_after_end() {
+        let mut segmenter = SpeechSegmenter::new(20, 1000);
+        assert_eq!(segmenter.process(true, BLOCK), Some(SpeechTransition::Start));
+        assert_eq!(segmenter.process(false, 2 * BLOCK), Some(SpeechTransition::End));
+        assert_eq!(segmenter.process(true, BLOCK), Some(SpeechTransition::Start));
+        assert_eq!(segmenter.process(false, BLOCK), None);
+        assert_eq!(segmenter.process(false, BLOCK), Some(SpeechTransition::End));
+    }
+}
@@ -0,0 +1,82 @@
+//! Local wake-word detection stage in the recording pipeline.
+//!
+//! Detectors implement [`WakeWordDetector`] and receive mono PCM at their own
+//! sample rate. [`WakeWordStage`] sits in the recording thread after the Speex
+//! preprocessor and before Opus encoding: it downmixes the per-channel capture
+//! buffers, resamples them to the detector rate and reports detections.
+
+use anyhow::Result;
+
+use super::audio_system::AudioConfig;
+use super::stream_decoder::PcmConverter;
+use super::template_kws::TemplateDetector;
+
+/// A keyword spotter fed with captured microphone audio.
+pub trait WakeWordDetector: Send {
+    /// Sample rate of the mono PCM expected by `process`.
+    fn sample_rate(&self) -> u32;
+
+    /// Feed a block of mono PCM. Returns the wake word text when it fires.
+    fn process(&mut self, pcm: &[i16]) -> Option<String>;
+
+    /// Forget buffered audio (e.g. after a detection).
+    fn reset(&mut self);
+}
+
+/// Factory function: create the configured wake-word detector.
+fn create_detector(config: &AudioConfig) -> Result<Box<dyn WakeWordDetector>> {
+    let detector = TemplateDetector::new(
+        &config.wake_word,
+        &config.wake_word_templates,
+        config.wake_word_threshold,
+    )?;
+    Ok(Box::new(detector))
+}
+
+/// Adapts capture-format audio to a [`WakeWordDetector`].
+pub struct WakeWordStage {
+    detector: Box<dyn WakeWordDetector>,
+    converter: PcmConverter,
+    mono: Vec<i16>,
+}
+
+impl WakeWordStage {
+    /// * `config`       - Audio configuration (wake word and templates)
+    /// * `capture_rate` - Negotiated ALSA capture sample rate
+    pub fn new(config: &AudioConfig, capture_rate: u32) -> Result<Self> {
+        let detector = create_detector(config)?;
+        let converter = PcmConverter::new(capture_rate, 1, detector.sample_rate(), 1)?;
+        Ok(Self {
+            detector,
+            converter,
+            mono: Vec::new(),
+        })
+    }
+
+    /// Feed one block of per-channel buffers (`frames` samples each).
+    pub fn process(&mut self, channels: &[Vec<i16>], frames: usize) -> Option<String> {
+        if channels.is_empty() || frames == 0 {
+            return None;
+        }
+
+        self.mono.clear();
+        self.mono.extend((0..frames).map(|i| {
+            let sum: i32 = channels.iter().map(|ch| ch[i] as i32).sum();
+            (sum / channels.len() as i32) as i16
+        }));
+
+        let pcm = match self.converter.process(&self.mono) {
+            Ok(pcm) => pcm,
+            Err(e) => {
+                log::warn!("Wake word resample error: {}", e);
+                return None;
+            }
+        };
+
+        let detected = self.detector.process(&pcm);
+        if detected.is_some() {
+            self.detector.reset();
+        }
+        detected
+    }
+}
@@ -0,0 +1,61 @@
+//! Minimal RIFF/WAVE reader for bundled 16-bit PCM clips (wake-word
+//! templates, voice prompts).
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Read a 16-bit PCM WAV file. Returns `(sample_rate, channels, samples)`.
+pub fn read_wav(path: &Path) -> Result<(u32, u32, Vec<i16>)> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_wav(&bytes, path)
+}
+
+/// Parse an in-memory 16-bit PCM WAV file; `path` is only used in errors.
+pub fn parse_wav(bytes: &[u8], path: &Path) -> Result<(u32, u32, Vec<i16>)> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        anyhow::bail!("{} is not a RIFF/WAVE file", path.display());
+    }
+
+    let mut format: Option<(u32, u32)> = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into()?) as usize;
+        let body_start = offset + 8;
+        let body = &bytes[body_start..(body_start + size).min(bytes.len())];
+
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]) as u32;
+                let rate = u32::from_le_bytes(body[4..8].try_into()?);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                // 1 = PCM, 0xFFFE = WAVE_FORMAT_EXTENSIBLE
+                if (tag != 1 && tag != 0xFFFE) || bits != 16 || channels == 0 {
+                    anyhow::bail!(
+                        "{}: only 16-bit PCM WAV is supported (format={}, bits={})",
+                        path.display(),
+                        tag,
+                        bits
+                    );
+                }
+                format = Some((rate, channels));
+            }
+            b"data" => {
+                let (rate, channels) = format
+                    .with_context(|| format!("{}: data chunk before fmt chunk", path.display()))?;
+                let samples = body
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect();
+                return Ok((rate, channels, samples));
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        offset = body_start + size + (size & 1);
+    }
+    anyhow::bail!("{}: no data chunk", path.display())
+}
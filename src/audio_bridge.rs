@@ -1,20 +1,32 @@
-use crate::config::Config;
+use crate::config::{Config, PcmEndian};
 use tokio::sync::mpsc;
-use crate::audio::{AudioConfig, AudioSystem};
+use crate::audio::{AudioConfig, AudioSystem, CaptureEvent, DecoderParams, PlaybackPacket, PromptClip};
 
 pub enum AudioEvent {
-    AudioData(Vec<u8>),
+    /// 编码后的录音数据，timestamp 为录音时正在播放的下行音频时间戳（用于服务器端 AEC）
+    AudioData { data: Vec<u8>, timestamp: u32 },
+    /// 播报期间检测到用户说话（用于播报打断）
+    BargeIn,
+    /// 本地 VAD 检测到用户开始说话
+    SpeechStart,
+    /// 本地 VAD 检测到用户停止说话
+    SpeechEnd,
+    /// 本地唤醒词检测命中，携带唤醒词文本
+    WakeWordDetected(String),
 }
 
 pub struct AudioBridge {
-    _audio_system: AudioSystem,
-    play_tx: mpsc::Sender<Vec<u8>>,
+    audio_system: AudioSystem,
+    play_tx: mpsc::Sender<PlaybackPacket>,
 }
 
 impl AudioBridge {
     /// Start the integrated audio system (replaces the external sound_app process).
     ///
-    /// Recording data is forwarded as `AudioEvent::AudioData` via `tx`.
+    /// Recording data is forwarded as `AudioEvent::AudioData` via `tx`,
+    /// VAD transitions as `AudioEvent::SpeechStart` / `SpeechEnd`, barge-in
+    /// onsets as `AudioEvent::BargeIn` and wake words as
+    /// `AudioEvent::WakeWordDetected`.
     /// Call `send_audio()` to send Opus packets for playback.
     pub fn start(config: &Config, tx: mpsc::Sender<AudioEvent>) -> anyhow::Result<Self> {
         let audio_config = AudioConfig {
@@ -28,41 +40,141 @@ impl AudioBridge {
             encode_frame_duration_ms: 20,
             decode_frame_duration_ms: config.hello_frame_duration,
             stream_format: config.stream_format.as_str().to_string(),
+            pcm_sample_rate: config.pcm_sample_rate,
+            pcm_channels: config.pcm_channels,
+            pcm_big_endian: config.pcm_endian == PcmEndian::Big,
             playback_sample_rate: config.playback_sample_rate,
             playback_channels: config.playback_channels,
             playback_period_size: config.playback_period_size,
+            aec_enabled: config.enable_aec,
+            aec_filter_length_ms: config.aec_filter_length_ms,
+            barge_in_enabled: config.enable_barge_in,
+            barge_in_threshold_db: config.barge_in_threshold_db,
+            barge_in_min_speech_ms: config.barge_in_min_speech_ms,
+            vad_hangover_ms: config.vad_hangover_ms,
+            wake_word_enabled: config.enable_wake_word,
+            wake_word: config.wake_word.to_string(),
+            wake_word_templates: config.wake_word_templates.clone(),
+            wake_word_threshold: config.wake_word_threshold,
         };
 
-        let (opus_tx, mut opus_rx) = mpsc::channel::<Vec<u8>>(100);
-        let (play_tx, play_rx) = mpsc::channel::<Vec<u8>>(100);
+        let (capture_tx, mut capture_rx) = mpsc::channel::<CaptureEvent>(100);
+        let (play_tx, play_rx) = mpsc::channel::<PlaybackPacket>(100);
 
         log::info!(
             "AudioBridge: capture_device=\"{}\", playback_device=\"{}\"",
             audio_config.capture_device, audio_config.playback_device,
         );
 
-        let audio_system = AudioSystem::start(audio_config, opus_tx, play_rx)?;
+        let audio_system = AudioSystem::start(audio_config, capture_tx, play_rx)?;
 
-        // Forward recording Opus data as AudioEvent
+        // Forward recording events as AudioEvent
         tokio::spawn(async move {
-            while let Some(data) = opus_rx.recv().await {
-                if tx.send(AudioEvent::AudioData(data)).await.is_err() {
+            while let Some(event) = capture_rx.recv().await {
+                let event = match event {
+                    CaptureEvent::Encoded { data, timestamp } => {
+                        AudioEvent::AudioData { data, timestamp }
+                    }
+                    CaptureEvent::BargeIn => AudioEvent::BargeIn,
+                    CaptureEvent::SpeechStart => AudioEvent::SpeechStart,
+                    CaptureEvent::SpeechEnd => AudioEvent::SpeechEnd,
+                    CaptureEvent::WakeWord(word) => AudioEvent::WakeWordDetected(word),
+                };
+                if tx.send(event).await.is_err() {
                     break;
                 }
             }
         });
 
         Ok(Self {
-            _audio_system: audio_system,
+            audio_system,
             play_tx,
         })
     }
 
+    /// A bridge without audio devices; queued playback packets go to the
+    /// returned receiver.
+    #[cfg(test)]
+    pub fn detached() -> (Self, mpsc::Receiver<PlaybackPacket>) {
+        let (play_tx, play_rx) = mpsc::channel(100);
+        let audio_system = AudioSystem::detached(&AudioConfig::default());
+        (Self { audio_system, play_tx }, play_rx)
+    }
+
     /// Send an Opus packet for playback.
-    pub async fn send_audio(&self, data: &[u8]) -> anyhow::Result<()> {
+    ///
+    /// `timestamp` is the server timestamp of the packet (0 if none); it is
+    /// echoed back on recorded packets captured while it plays.
+    pub async fn send_audio(&self, data: Vec<u8>, timestamp: u32) -> anyhow::Result<()> {
+        let packet = PlaybackPacket {
+            generation: self.audio_system.playback_generation(),
+            timestamp,
+            data,
+            pcm_format: None,
+        };
         self.play_tx
-            .send(data.to_vec())
+            .send(packet)
             .await
             .map_err(|e| anyhow::anyhow!("Failed to send audio for playback: {}", e))
     }
+
+    /// Queue prerecorded voice prompt clips for playback, in order.
+    ///
+    /// Clips are split into ~100 ms packets so that `stop_playback()` can cut
+    /// them off promptly.
+    pub async fn play_prompts(&self, clips: &[&PromptClip]) -> anyhow::Result<()> {
+        let generation = self.audio_system.playback_generation();
+        for clip in clips {
+            let samples_per_packet =
+                (clip.format.sample_rate / 10 * clip.format.channels).max(1) as usize;
+            for chunk in clip.samples.chunks(samples_per_packet) {
+                let packet = PlaybackPacket {
+                    generation,
+                    timestamp: 0,
+                    data: chunk.iter().flat_map(|s| s.to_le_bytes()).collect(),
+                    pcm_format: Some(clip.format),
+                };
+                self.play_tx
+                    .send(packet)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to send prompt for playback: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adopt the downstream audio format negotiated with the server.
+    ///
+    /// Rebuilds the playback decoder before the next packet if the format
+    /// differs from the current one.
+    pub fn reconfigure_decoder(&self, sample_rate: u32, channels: u32, frame_duration_ms: u32) {
+        let params = DecoderParams {
+            sample_rate,
+            channels,
+            frame_duration_ms,
+        };
+        if self.audio_system.reconfigure_decoder(params) {
+            log::info!(
+                "AudioBridge: server audio format changed to {}Hz/{}ch/{}ms, reconfiguring decoder",
+                sample_rate,
+                channels,
+                frame_duration_ms
+            );
+        }
+    }
+
+    /// Drop all audio queued for playback (e.g. when the user interrupts TTS).
+    ///
+    /// Stale packets still in the channel are discarded, the ALSA buffer is
+    /// dropped and the decoder is reset by the playback thread.
+    pub fn stop_playback(&self) {
+        let generation = self.audio_system.stop_playback();
+        // 空包用于唤醒阻塞在 recv 上的播放线程；通道已满时线程本来就会被唤醒
+        let _ = self.play_tx.try_send(PlaybackPacket {
+            generation,
+            timestamp: 0,
+            data: Vec::new(),
+            pcm_format: None,
+        });
+    }
 }
@@ -0,0 +1,75 @@
+//! clock - 应用层时钟
+//!
+//! 没有 RTC 的板子开机时系统时间可能停在 1970 年，而 NTP 往往还没同步。
+//! OTA 响应中的 server_time 记录为相对单调时钟的偏移，应用层时间戳和 TLS 证书有效期校验
+//! 都使用校准后的时间；校准前以编译时间作为时间下限
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 系统时间与服务器时间相差超过该值时才修改系统时间
+const SYSTEM_TIME_TOLERANCE: Duration = Duration::from_secs(60);
+
+// 校准时的单调时钟读数和对应的服务器 Unix 时间
+static SERVER_TIME: Mutex<Option<(Instant, Duration)>> = Mutex::new(None);
+
+/// 用服务器下发的 Unix 时间戳（毫秒）校准应用层时钟
+pub fn sync(server_timestamp_ms: u64, timezone_offset_min: i32) {
+    let server = Duration::from_millis(server_timestamp_ms);
+    let system = system_now();
+    let skew_ms = server.as_millis() as i64 - system.as_millis() as i64;
+    log::info!(
+        "Clock synced with server: timezone offset {} min, system clock skew {}ms",
+        timezone_offset_min,
+        skew_ms
+    );
+    *SERVER_TIME.lock().unwrap() = Some((Instant::now(), server));
+}
+
+/// 是否已用服务器时间校准
+pub fn is_synced() -> bool {
+    SERVER_TIME.lock().unwrap().is_some()
+}
+
+/// 当前 Unix 时间：已校准时为服务器时间，否则为系统时间（不早于编译时间）
+pub fn unix_now() -> Duration {
+    if let Some((synced_at, server)) = *SERVER_TIME.lock().unwrap() {
+        return server + synced_at.elapsed();
+    }
+    system_now().max(build_time())
+}
+
+/// 系统时间与已校准的时间相差较大时修改系统时间，需要 CAP_SYS_TIME 权限
+pub fn apply_to_system() {
+    if !is_synced() {
+        return;
+    }
+    let now = unix_now();
+    if now.abs_diff(system_now()) < SYSTEM_TIME_TOLERANCE {
+        return;
+    }
+
+    let tv = libc::timeval {
+        tv_sec: now.as_secs() as libc::time_t,
+        tv_usec: now.subsec_micros() as libc::suseconds_t,
+    };
+    // SAFETY: tv 为合法的 timeval，时区参数允许为空指针
+    if unsafe { libc::settimeofday(&tv, std::ptr::null()) } == 0 {
+        log::info!("System time set from server time");
+    } else {
+        log::warn!(
+            "Failed to set system time: {} (requires CAP_SYS_TIME)",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+fn system_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn build_time() -> Duration {
+    Duration::from_secs(env!("BUILD_TIMESTAMP").parse().unwrap_or(0))
+}
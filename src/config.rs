@@ -2,6 +2,7 @@ use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, fs, path::Path};
 use uuid::Uuid;
+use crate::device_info;
 use crate::mcp_gateway::ExternalToolConfig;
 
 const CONFIG_FILE_NAME: &str = "xiaozhi_config.json";
@@ -31,6 +32,92 @@ impl std::fmt::Display for AudioStreamFormat {
     }
 }
 
+/// 原始 PCM 流的字节序
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PcmEndian {
+    Little,
+    Big,
+}
+
+/// listen 消息的拾音模式
+/// - auto: 服务器 VAD 判定说话结束
+/// - manual: 客户端决定何时发送 listen stop
+/// - realtime: 持续拾音（需要 AEC 支持边播边听）
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenMode {
+    Auto,
+    Manual,
+    Realtime,
+}
+
+impl ListenMode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Auto => "auto",
+            Self::Manual => "manual",
+            Self::Realtime => "realtime",
+        }
+    }
+}
+
+/// 与服务器通信使用的传输协议
+/// - websocket: 控制消息与音频都走 WebSocket
+/// - mqtt: 控制消息走 MQTT，音频走加密 UDP
+/// - auto: OTA 下发了 MQTT 配置时使用 mqtt，否则使用 websocket
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportType {
+    WebSocket,
+    Mqtt,
+    Auto,
+}
+
+impl TransportType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::WebSocket => "websocket",
+            Self::Mqtt => "mqtt",
+            Self::Auto => "auto",
+        }
+    }
+}
+
+/// MQTT 连接参数，可在 config.toml 中配置，也可由 OTA 响应下发
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// 形如 "mqtt.xiaozhi.me:8883"，未写端口时默认 8883
+    pub endpoint: String,
+    pub client_id: String,
+    pub username: String,
+    pub password: String,
+    pub publish_topic: String,
+    pub subscribe_topic: String,
+}
+
+impl MqttConfig {
+    pub fn is_configured(&self) -> bool {
+        !self.endpoint.trim().is_empty()
+    }
+}
+
+/// 出站连接设置，WebSocket、MQTT、激活请求和 MCP HTTP 工具共用
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct OutboundConfig {
+    /// 代理地址，支持 "http://host:port" 和 "socks5://host:port"，可带 user:pass@；为空表示直连
+    pub proxy: String,
+    /// 时钟未校准时，容忍生效时间在该窗口内的"尚未生效"证书（秒，0 关闭）
+    pub not_yet_valid_window_secs: u64,
+    /// 额外信任的 CA 证书（PEM，可包含多张），与内置根证书一起使用
+    pub ca_file: String,
+    /// 双向 TLS 的客户端证书链和私钥（PEM），两者需同时配置
+    pub client_cert_file: String,
+    pub client_key_file: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct McpConfig {
     pub enabled: bool,
@@ -38,7 +125,9 @@ pub struct McpConfig {
     pub tools: Vec<ExternalToolConfig>,
 }
 
+// 旧版本的 xiaozhi_config.json 可能缺少新增字段，缺省时使用编译时默认值
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Config {
     // 音频设备配置
     pub capture_device: Cow<'static, str>,
@@ -47,6 +136,33 @@ pub struct Config {
     pub playback_sample_rate: u32,
     pub playback_channels: u32,
     pub playback_period_size: usize,
+    // 原始PCM源流参数（仅 stream_format = "pcm" 时使用）
+    pub pcm_sample_rate: u32,
+    pub pcm_channels: u32,
+    pub pcm_endian: PcmEndian,
+    // 回声消除滤波器尾长（毫秒）
+    pub aec_filter_length_ms: u32,
+    // 播报打断的能量检测参数
+    pub barge_in_threshold_db: i32,
+    pub barge_in_min_speech_ms: u32,
+    // 本地 VAD 判定说话结束的静音时长（毫秒）
+    pub vad_hangover_ms: u32,
+
+    // 拾音模式配置
+    pub listen_mode: ListenMode,
+    pub ptt_input_device: Cow<'static, str>,
+    pub ptt_key_code: u16,
+
+    // 本地唤醒词配置
+    pub wake_word: Cow<'static, str>,
+    pub wake_word_templates: Vec<String>,
+    pub wake_word_threshold: f32,
+
+    // 本地语音提示：预录制语音目录，未激活时播报验证码的最短间隔（秒，0 关闭），
+    // 缺少录音时是否用内置的 DTMF 按键音代替
+    pub voice_prompt_dir: Cow<'static, str>,
+    pub activation_code_prompt_interval_secs: u32,
+    pub voice_prompt_tone_fallback: bool,
 
     // GUI进程配置
     pub gui_local_port: u16,
@@ -59,6 +175,24 @@ pub struct Config {
     pub ws_url: Cow<'static, str>,
     pub ota_url: Cow<'static, str>,
     pub ws_token: Cow<'static, str>,
+    // 激活协议 v2 的设备身份文件，为空时使用 v1 激活
+    pub activation_identity_file: Cow<'static, str>,
+    // WebSocket 二进制帧协议版本（1-3）
+    pub ws_protocol_version: u8,
+    // WebSocket 心跳：Ping 间隔、Pong 超时、读空闲超时（秒，0 表示关闭）
+    pub ws_ping_interval_secs: u32,
+    pub ws_pong_timeout_secs: u32,
+    pub ws_read_timeout_secs: u32,
+    // 断线重连退避：最短 / 最长等待，连接稳定多久后重置退避（秒）
+    pub reconnect_min_secs: u32,
+    pub reconnect_max_secs: u32,
+    pub reconnect_stable_secs: u32,
+    pub transport: TransportType,
+    pub mqtt: MqttConfig,
+    // 代理与自定义证书
+    pub outbound: OutboundConfig,
+    // OTA 请求中不上报的设备信息字段
+    pub device_info_exclude: Vec<String>,
 
     // 设备标识（动态部分，可在运行时修改）
     pub device_id: String,
@@ -72,6 +206,13 @@ pub struct Config {
 
     // 功能开关
     pub enable_tts_display: bool,
+    pub enable_emotion_display: bool,
+    pub enable_aec: bool,
+    pub enable_barge_in: bool,
+    pub enable_wake_word: bool,
+    pub enable_ota_upgrade: bool,
+    // 用服务器时间修改系统时间
+    pub enable_system_time_sync: bool,
 
     // MCP配置
     pub mcp: McpConfig,
@@ -93,6 +234,23 @@ impl Config {
             "pcm" => AudioStreamFormat::Pcm,
             _ => return Err("Invalid AUDIO_STREAM_FORMAT value"),
         };
+        let pcm_endian = match env!("AUDIO_PCM_ENDIAN") {
+            "little" => PcmEndian::Little,
+            "big" => PcmEndian::Big,
+            _ => return Err("Invalid AUDIO_PCM_ENDIAN value"),
+        };
+        let listen_mode = match env!("LISTEN_MODE") {
+            "auto" => ListenMode::Auto,
+            "manual" => ListenMode::Manual,
+            "realtime" => ListenMode::Realtime,
+            _ => return Err("Invalid LISTEN_MODE value"),
+        };
+        let transport = match env!("NETWORK_TRANSPORT") {
+            "websocket" => TransportType::WebSocket,
+            "mqtt" => TransportType::Mqtt,
+            "auto" => TransportType::Auto,
+            _ => return Err("Invalid NETWORK_TRANSPORT value"),
+        };
 
         Ok(Self {
             // 音频设备配置
@@ -108,6 +266,49 @@ impl Config {
             playback_period_size: env!("AUDIO_PLAYBACK_PERIOD_SIZE")
                 .parse()
                 .map_err(|_| "Failed to parse AUDIO_PLAYBACK_PERIOD_SIZE")?,
+            pcm_sample_rate: env!("AUDIO_PCM_SAMPLE_RATE")
+                .parse()
+                .map_err(|_| "Failed to parse AUDIO_PCM_SAMPLE_RATE")?,
+            pcm_channels: env!("AUDIO_PCM_CHANNELS")
+                .parse()
+                .map_err(|_| "Failed to parse AUDIO_PCM_CHANNELS")?,
+            pcm_endian,
+            aec_filter_length_ms: env!("AUDIO_AEC_FILTER_LENGTH_MS")
+                .parse()
+                .map_err(|_| "Failed to parse AUDIO_AEC_FILTER_LENGTH_MS")?,
+            barge_in_threshold_db: env!("AUDIO_BARGE_IN_THRESHOLD_DB")
+                .parse()
+                .map_err(|_| "Failed to parse AUDIO_BARGE_IN_THRESHOLD_DB")?,
+            barge_in_min_speech_ms: env!("AUDIO_BARGE_IN_MIN_SPEECH_MS")
+                .parse()
+                .map_err(|_| "Failed to parse AUDIO_BARGE_IN_MIN_SPEECH_MS")?,
+            vad_hangover_ms: env!("AUDIO_VAD_HANGOVER_MS")
+                .parse()
+                .map_err(|_| "Failed to parse AUDIO_VAD_HANGOVER_MS")?,
+
+            // 拾音模式配置
+            listen_mode,
+            ptt_input_device: Cow::Borrowed(env!("PTT_INPUT_DEVICE")),
+            ptt_key_code: env!("PTT_KEY_CODE")
+                .parse()
+                .map_err(|_| "Failed to parse PTT_KEY_CODE")?,
+
+            // 本地唤醒词配置
+            wake_word: Cow::Borrowed(env!("WAKE_WORD")),
+            wake_word_templates: serde_json::from_str(env!("WAKE_WORD_TEMPLATES_JSON"))
+                .map_err(|_| "Failed to parse WAKE_WORD_TEMPLATES_JSON")?,
+            wake_word_threshold: env!("WAKE_WORD_THRESHOLD")
+                .parse()
+                .map_err(|_| "Failed to parse WAKE_WORD_THRESHOLD")?,
+
+            // 本地语音提示配置
+            voice_prompt_dir: Cow::Borrowed(env!("VOICE_PROMPT_DIR")),
+            activation_code_prompt_interval_secs: env!("ACTIVATION_CODE_PROMPT_INTERVAL_SECS")
+                .parse()
+                .map_err(|_| "Failed to parse ACTIVATION_CODE_PROMPT_INTERVAL_SECS")?,
+            voice_prompt_tone_fallback: env!("VOICE_PROMPT_TONE_FALLBACK")
+                .parse()
+                .map_err(|_| "Failed to parse VOICE_PROMPT_TONE_FALLBACK")?,
 
             // GUI进程配置
             gui_local_port: env!("GUI_LOCAL_PORT")
@@ -126,6 +327,48 @@ impl Config {
             ws_url: Cow::Borrowed(env!("WS_URL")),
             ota_url: Cow::Borrowed(env!("OTA_URL")),
             ws_token: Cow::Borrowed(env!("WS_TOKEN")),
+            activation_identity_file: Cow::Borrowed(env!("ACTIVATION_IDENTITY_FILE")),
+            ws_protocol_version: env!("WS_PROTOCOL_VERSION")
+                .parse()
+                .map_err(|_| "Failed to parse WS_PROTOCOL_VERSION")?,
+            ws_ping_interval_secs: env!("WS_PING_INTERVAL_SECS")
+                .parse()
+                .map_err(|_| "Failed to parse WS_PING_INTERVAL_SECS")?,
+            ws_pong_timeout_secs: env!("WS_PONG_TIMEOUT_SECS")
+                .parse()
+                .map_err(|_| "Failed to parse WS_PONG_TIMEOUT_SECS")?,
+            ws_read_timeout_secs: env!("WS_READ_TIMEOUT_SECS")
+                .parse()
+                .map_err(|_| "Failed to parse WS_READ_TIMEOUT_SECS")?,
+            reconnect_min_secs: env!("RECONNECT_MIN_SECS")
+                .parse()
+                .map_err(|_| "Failed to parse RECONNECT_MIN_SECS")?,
+            reconnect_max_secs: env!("RECONNECT_MAX_SECS")
+                .parse()
+                .map_err(|_| "Failed to parse RECONNECT_MAX_SECS")?,
+            reconnect_stable_secs: env!("RECONNECT_STABLE_SECS")
+                .parse()
+                .map_err(|_| "Failed to parse RECONNECT_STABLE_SECS")?,
+            transport,
+            mqtt: MqttConfig {
+                endpoint: env!("MQTT_ENDPOINT").to_string(),
+                client_id: env!("MQTT_CLIENT_ID").to_string(),
+                username: env!("MQTT_USERNAME").to_string(),
+                password: env!("MQTT_PASSWORD").to_string(),
+                publish_topic: env!("MQTT_PUBLISH_TOPIC").to_string(),
+                subscribe_topic: env!("MQTT_SUBSCRIBE_TOPIC").to_string(),
+            },
+            device_info_exclude: serde_json::from_str(env!("DEVICE_INFO_EXCLUDE_JSON"))
+                .map_err(|_| "Failed to parse DEVICE_INFO_EXCLUDE_JSON")?,
+            outbound: OutboundConfig {
+                proxy: env!("OUTBOUND_PROXY").to_string(),
+                not_yet_valid_window_secs: env!("OUTBOUND_NOT_YET_VALID_WINDOW_SECS")
+                    .parse()
+                    .map_err(|_| "Failed to parse OUTBOUND_NOT_YET_VALID_WINDOW_SECS")?,
+                ca_file: env!("OUTBOUND_CA_FILE").to_string(),
+                client_cert_file: env!("OUTBOUND_CLIENT_CERT_FILE").to_string(),
+                client_key_file: env!("OUTBOUND_CLIENT_KEY_FILE").to_string(),
+            },
 
             // 设备标识初始化为config.toml中的值
             device_id: env!("DEVICE_ID").to_string(),
@@ -147,6 +390,24 @@ impl Config {
             enable_tts_display: env!("ENABLE_TTS_DISPLAY")
                 .parse()
                 .map_err(|_| "Failed to parse ENABLE_TTS_DISPLAY")?,
+            enable_emotion_display: env!("ENABLE_EMOTION_DISPLAY")
+                .parse()
+                .map_err(|_| "Failed to parse ENABLE_EMOTION_DISPLAY")?,
+            enable_aec: env!("ENABLE_AEC")
+                .parse()
+                .map_err(|_| "Failed to parse ENABLE_AEC")?,
+            enable_barge_in: env!("ENABLE_BARGE_IN")
+                .parse()
+                .map_err(|_| "Failed to parse ENABLE_BARGE_IN")?,
+            enable_wake_word: env!("ENABLE_WAKE_WORD")
+                .parse()
+                .map_err(|_| "Failed to parse ENABLE_WAKE_WORD")?,
+            enable_ota_upgrade: env!("ENABLE_OTA_UPGRADE")
+                .parse()
+                .map_err(|_| "Failed to parse ENABLE_OTA_UPGRADE")?,
+            enable_system_time_sync: env!("ENABLE_SYSTEM_TIME_SYNC")
+                .parse()
+                .map_err(|_| "Failed to parse ENABLE_SYSTEM_TIME_SYNC")?,
 
             // MCP配置
             mcp: serde_json::from_str(env!("MCP_CONFIG_JSON"))
@@ -162,12 +423,27 @@ impl Config {
                 log::info!("音频流格式校验通过: opus");
             }
             AudioStreamFormat::Mp3 => {
-                anyhow::bail!(
-                    "配置错误：当前版本尚未支持 mp3 格式解码，请改回 opus"
-                );
+                log::info!("音频流格式校验通过: mp3");
             }
             AudioStreamFormat::Pcm => {
-                log::info!("音频流格式校验通过: pcm (原始PCM直通)");
+                if self.pcm_sample_rate < 8000 || self.pcm_sample_rate > 192000 {
+                    anyhow::bail!(
+                        "配置错误：PCM源采样率 {}Hz 不合法 (支持 8000-192000)",
+                        self.pcm_sample_rate
+                    );
+                }
+                if self.pcm_channels == 0 || self.pcm_channels > 8 {
+                    anyhow::bail!(
+                        "配置错误：PCM源声道数 {} 不合法 (支持 1-8)",
+                        self.pcm_channels
+                    );
+                }
+                log::info!(
+                    "音频流格式校验通过: pcm (原始PCM直通, {}Hz/{}ch/{:?})",
+                    self.pcm_sample_rate,
+                    self.pcm_channels,
+                    self.pcm_endian
+                );
             }
         }
 
@@ -179,6 +455,156 @@ impl Config {
             );
         }
 
+        if self.enable_aec && (self.aec_filter_length_ms < 20 || self.aec_filter_length_ms > 1000) {
+            anyhow::bail!(
+                "配置错误：回声消除滤波器尾长 {}ms 不合法 (支持 20-1000)",
+                self.aec_filter_length_ms
+            );
+        }
+
+        if self.enable_barge_in && !self.enable_aec {
+            anyhow::bail!("配置错误：enable_barge_in 需要同时开启 enable_aec");
+        }
+
+        if self.barge_in_threshold_db > 0 {
+            anyhow::bail!(
+                "配置错误：打断能量阈值 {}dBFS 不合法 (应 <= 0)",
+                self.barge_in_threshold_db
+            );
+        }
+
+        if self.vad_hangover_ms < 100 || self.vad_hangover_ms > 5000 {
+            anyhow::bail!(
+                "配置错误：VAD 静音判定时长 {}ms 不合法 (支持 100-5000)",
+                self.vad_hangover_ms
+            );
+        }
+
+        if self.listen_mode == ListenMode::Realtime && !self.enable_aec {
+            anyhow::bail!("配置错误：realtime 拾音模式需要同时开启 enable_aec");
+        }
+
+        if !(1..=3).contains(&self.ws_protocol_version) {
+            anyhow::bail!(
+                "配置错误：WebSocket 协议版本 {} 不合法 (支持 1-3)",
+                self.ws_protocol_version
+            );
+        }
+
+        if self.ws_ping_interval_secs > 0
+            && (self.ws_pong_timeout_secs == 0 || self.ws_pong_timeout_secs > self.ws_ping_interval_secs)
+        {
+            anyhow::bail!(
+                "配置错误：Pong 超时 {}s 不合法 (应在 1 到 ping_interval_secs={} 之间)",
+                self.ws_pong_timeout_secs,
+                self.ws_ping_interval_secs
+            );
+        }
+
+        if self.ws_read_timeout_secs > 0 && self.ws_read_timeout_secs <= self.ws_ping_interval_secs {
+            anyhow::bail!(
+                "配置错误：读空闲超时 {}s 应大于 Ping 间隔 {}s",
+                self.ws_read_timeout_secs,
+                self.ws_ping_interval_secs
+            );
+        }
+
+        if self.reconnect_min_secs == 0 || self.reconnect_max_secs < self.reconnect_min_secs {
+            anyhow::bail!(
+                "配置错误：重连等待时间 {}-{}s 不合法 (应满足 1 <= min <= max)",
+                self.reconnect_min_secs,
+                self.reconnect_max_secs
+            );
+        }
+
+        if self.transport == TransportType::Mqtt && !self.mqtt.is_configured() {
+            log::warn!("transport = \"mqtt\" 但未配置 [mqtt] endpoint，将等待 OTA 下发 MQTT 配置");
+        }
+
+        if let Some(unknown) = self
+            .device_info_exclude
+            .iter()
+            .find(|field| !device_info::FIELDS.contains(&field.as_str()))
+        {
+            anyhow::bail!(
+                "配置错误：device_info exclude 中的字段 {} 不存在 (支持 {})",
+                unknown,
+                device_info::FIELDS.join(", ")
+            );
+        }
+
+        if !self.activation_identity_file.is_empty()
+            && !Path::new(self.activation_identity_file.as_ref()).exists()
+        {
+            anyhow::bail!(
+                "配置错误：激活身份文件 {} 不存在",
+                self.activation_identity_file
+            );
+        }
+
+        let proxy = self.outbound.proxy.trim();
+        if !proxy.is_empty() && !proxy.starts_with("http://") && !proxy.starts_with("socks5://") {
+            anyhow::bail!(
+                "配置错误：代理地址 {} 不合法 (支持 http:// 和 socks5://)",
+                proxy
+            );
+        }
+        // rumqttc 只支持 HTTP CONNECT 代理
+        if proxy.starts_with("socks5://") && self.transport == TransportType::Mqtt {
+            anyhow::bail!("配置错误：MQTT 传输不支持 socks5 代理，请改用 http:// 代理或 websocket 传输");
+        }
+        if self.outbound.client_cert_file.is_empty() != self.outbound.client_key_file.is_empty() {
+            anyhow::bail!("配置错误：client_cert_file 和 client_key_file 需要同时配置");
+        }
+        if let Some(missing) = [
+            &self.outbound.ca_file,
+            &self.outbound.client_cert_file,
+            &self.outbound.client_key_file,
+        ]
+        .into_iter()
+        .find(|p| !p.is_empty() && !Path::new(p.as_str()).exists())
+        {
+            anyhow::bail!("配置错误：证书文件 {} 不存在", missing);
+        }
+
+        if self.enable_wake_word {
+            if self.wake_word.trim().is_empty() {
+                anyhow::bail!("配置错误：启用唤醒词时 wake_word 不能为空");
+            }
+            if self.wake_word_templates.is_empty() {
+                anyhow::bail!("配置错误：启用唤醒词时至少需要一个唤醒词模板录音");
+            }
+            if let Some(missing) = self
+                .wake_word_templates
+                .iter()
+                .find(|p| !Path::new(p.as_str()).exists())
+            {
+                anyhow::bail!("配置错误：唤醒词模板文件 {} 不存在", missing);
+            }
+            if self.wake_word_threshold <= 0.0 {
+                anyhow::bail!(
+                    "配置错误：唤醒词阈值 {} 不合法 (应 > 0)",
+                    self.wake_word_threshold
+                );
+            }
+        }
+
+        if self.activation_code_prompt_interval_secs > 0
+            && !Path::new(self.voice_prompt_dir.as_ref()).is_dir()
+        {
+            if self.voice_prompt_tone_fallback {
+                log::warn!(
+                    "语音提示目录 {} 不存在，未激活时只能用 DTMF 按键音播报验证码",
+                    self.voice_prompt_dir
+                );
+            } else {
+                log::warn!(
+                    "语音提示目录 {} 不存在，未激活时不会播报验证码",
+                    self.voice_prompt_dir
+                );
+            }
+        }
+
         if self.playback_sample_rate < 8000 || self.playback_sample_rate > 192000 {
             anyhow::bail!(
                 "配置错误：播放采样率 {}Hz 不合法 (支持 8000-192000)",
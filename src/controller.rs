@@ -1,10 +1,12 @@
 use crate::audio_bridge::{AudioBridge, AudioEvent};
-use crate::config::Config;
+use crate::clock;
+use crate::config::{AudioStreamFormat, Config, ListenMode};
 use crate::gui_bridge::{GuiBridge, GuiEvent};
-use crate::net_link::{NetCommand, NetEvent};
-use crate::protocol::ServerMessage;
+use crate::key_input::KeyEvent;
+use crate::net_link::{AudioFrame, NetCommand, NetEvent};
+use crate::ota;
+use crate::protocol::{AudioParams, Emotion, ServerMessage, TtsState};
 use crate::state_machine::SystemState;
-use serde_json;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::process::Command;
@@ -13,7 +15,15 @@ use std::process::Stdio;
 pub struct CoreController {
     state: SystemState,
     current_session_id: Option<String>,
+    // 服务器 hello 握手完成，断线后复位；未连接时不上传麦克风音频
+    connected: bool,
     should_mute_mic: bool,
+    // 用户打断播报后，丢弃本轮剩余的 TTS 音频和状态，直到下一次 tts start
+    tts_aborted: bool,
+    // manual 模式：listen start 与 listen stop 之间才上传麦克风音频
+    listen_window_open: bool,
+    // manual 模式：按键（或 GUI）处于按下状态，此时由松开决定何时 listen stop
+    ptt_held: bool,
     config: Config,
     net_tx: mpsc::Sender<NetCommand>,
     audio_bridge: Arc<AudioBridge>,
@@ -30,7 +40,11 @@ impl CoreController {
         Self {
             state: SystemState::Idle,
             current_session_id: None,
+            connected: false,
             should_mute_mic: false,
+            tts_aborted: false,
+            listen_window_open: false,
+            ptt_held: false,
             config,
             net_tx,
             audio_bridge,
@@ -38,23 +52,103 @@ impl CoreController {
         }
     }
 
+    // 按服务器 hello 下发的 TTS 音频参数调整播放解码器
+    fn adopt_server_audio_params(&self, params: &AudioParams) {
+        let (config_rate, config_channels) = match self.config.stream_format {
+            // MP3 帧头自带采样率和声道数，无需重建解码器
+            AudioStreamFormat::Mp3 => return,
+            AudioStreamFormat::Pcm => {
+                // 裸 PCM 没有帧头，服务器未下发参数时保持用户配置
+                if params.sample_rate.is_none() && params.channels.is_none() {
+                    return;
+                }
+                (self.config.pcm_sample_rate, self.config.pcm_channels)
+            }
+            AudioStreamFormat::Opus => {
+                (self.config.hello_sample_rate, self.config.hello_channels as u32)
+            }
+        };
+        let sample_rate = params.sample_rate.unwrap_or(config_rate);
+        let channels = params.channels.map_or(config_channels, u32::from);
+        if (sample_rate, channels) != (config_rate, config_channels) {
+            log::warn!(
+                "Server {} audio {}Hz/{}ch differs from config {}Hz/{}ch, following server",
+                self.config.stream_format,
+                sample_rate,
+                channels,
+                config_rate,
+                config_channels
+            );
+        }
+        self.audio_bridge.reconfigure_decoder(
+            sample_rate,
+            channels,
+            params.frame_duration.unwrap_or(self.config.hello_frame_duration),
+        );
+    }
+
     // 处理来自 NetLink 的事件
     pub async fn handle_net_event(&mut self, event: NetEvent) {
         match event {
             NetEvent::Text(text) => self.process_server_text(text).await,
-            NetEvent::Binary(data) => self.process_server_audio(data).await,
-            NetEvent::Connected => {
-                log::info!("WebSocket Connected");
+            NetEvent::Binary(frame) => self.process_server_audio(frame).await,
+            NetEvent::Connected(session) => {
+                log::info!(
+                    "Connected ({}), session_id={}, server audio {:?}Hz/{:?}ch",
+                    session.transport,
+                    session.session_id,
+                    session.audio_params.sample_rate,
+                    session.audio_params.channels
+                );
+                self.connected = true;
+                // 与服务器握手成功，确认 OTA 升级后的新版本可用
+                ota::confirm_boot();
+                self.adopt_server_audio_params(&session.audio_params);
+                self.current_session_id = Some(session.session_id);
+                // 断线时进入 NetworkError，重连后回到空闲，本地 VAD 检测到说话时再切换到 Listening
+                self.state = SystemState::Idle;
                 if let Err(e) = self.gui_bridge.send_message(r#"{"state": 3}"#).await {
                     log::error!("Failed to send to GUI: {}", e);
                 }
+
+                if self.config.enable_wake_word || self.config.listen_mode == ListenMode::Manual {
+                    // 唤醒词 / manual 模式：保持空闲，检测到唤醒词或按键后再开始聆听
+                    log::info!(
+                        "Server Hello received. Waiting for wake word or push-to-talk..."
+                    );
+                    self.listen_window_open = false;
+                } else {
+                    log::info!("Server Hello received. Starting listen mode...");
+                    // 使用正确的 session_id 发送 listen 命令
+                    self.send_listen_start_command().await;
+                }
+            }
+            NetEvent::Rtt(rtt) => {
+                log::debug!("Link RTT: {}ms", rtt.as_millis());
+                let msg = serde_json::json!({"type": "network", "rtt_ms": rtt.as_millis() as u64});
+                if let Err(e) = self.gui_bridge.send_message(&msg.to_string()).await {
+                    log::error!("Failed to send to GUI: {}", e);
+                }
             }
-            NetEvent::Disconnected => {
-                log::info!("WebSocket Disconnected");
+            NetEvent::Disconnected { attempt, retry_in } => {
+                log::info!("Network Disconnected");
+                self.connected = false;
                 self.state = SystemState::NetworkError;
                 if let Err(e) = self.gui_bridge.send_message(r#"{"state": 4}"#).await {
                     log::error!("Failed to send to GUI: {}", e);
                 }
+
+                // 通知 GUI 下一次重连的时间，retry_at 为 Unix 毫秒时间戳
+                let retry_at = clock::unix_now() + retry_in;
+                let msg = serde_json::json!({
+                    "type": "reconnect",
+                    "attempt": attempt,
+                    "retry_in_ms": retry_in.as_millis() as u64,
+                    "retry_at": retry_at.as_millis() as u64,
+                });
+                if let Err(e) = self.gui_bridge.send_message(&msg.to_string()).await {
+                    log::error!("Failed to send to GUI: {}", e);
+                }
             }
         }
     }
@@ -65,164 +159,606 @@ impl CoreController {
 
         let msg: ServerMessage = match serde_json::from_str(&text) {
             Ok(msg) => msg,
-            Err(_) => {
-                // 可能不是JSON，忽略
+            Err(e) => {
+                // 可能不是JSON或字段不合法，忽略
+                log::warn!("Failed to parse server message: {}", e);
                 return;
             }
         };
 
-        if let Some(sid) = &msg.session_id {
-            if self.current_session_id.as_deref() != Some(sid) {
-                log::info!("New Session ID: {}", sid);
-                self.current_session_id = Some(sid.clone());
-            }
+        if let Some(sid) = msg.session_id()
+            && self.current_session_id.as_deref() != Some(sid)
+        {
+            log::info!("New Session ID: {}", sid);
+            self.current_session_id = Some(sid.to_string());
         }
 
-        match msg.msg_type.as_str() {
-            "hello" => {
-                log::info!("Server Hello received. Starting listen mode...");
-                // 使用正确的 session_id 发送 listen 命令
-                self.send_auto_listen_command().await;
+        match msg {
+            ServerMessage::Hello(_) => {
+                // 握手阶段的 hello 由传输层校验并以 Connected 事件通知，这里不应再收到
+                log::warn!("Unexpected server hello outside handshake, ignored");
             }
-            "iot" => {
-                if let Some(cmd) = &msg.command {
+            ServerMessage::Iot(iot) => {
+                for cmd in &iot.commands {
                     log::info!("Processing IoT Command: {}", cmd);
                 }
-                
-                // Fallback: 把接收到的完整 JSON 传递给外部脚本执行
-                let fallback_script = "./scripts/mcp_iot_fallback.sh";
-                let text_clone = text.clone();
-                tokio::spawn(async move {
-                    let mut child = match Command::new(fallback_script)
-                        .stdin(Stdio::piped())
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .spawn()
-                    {
-                        Ok(c) => c,
-                        Err(e) => {
-                            log::error!("Failed to spawn IoT fallback script {}: {}", fallback_script, e);
-                            return;
-                        }
-                    };
-                    
-                    if let Some(mut stdin) = child.stdin.take() {
-                        use tokio::io::AsyncWriteExt;
-                        if let Err(e) = stdin.write_all(text_clone.as_bytes()).await {
-                            log::error!("Failed to write to IoT fallback script stdin: {}", e);
-                        }
-                    }
-                    
-                    match child.wait_with_output().await {
-                        Ok(output) => {
-                            if !output.status.success() {
-                                let err_str = String::from_utf8_lossy(&output.stderr);
-                                log::error!("IoT fallback script failed: {}", err_str);
-                            } else {
-                                let out_str = String::from_utf8_lossy(&output.stdout);
-                                if !out_str.trim().is_empty() {
-                                    log::info!("IoT fallback script output: {}", out_str);
-                                }
+                self.run_iot_fallback(&text);
+            }
+            ServerMessage::Tts(tts) => {
+                if self.tts_aborted {
+                    match tts.state {
+                        TtsState::Start => self.tts_aborted = false,
+                        TtsState::Stop => {
+                            self.tts_aborted = false;
+                            // GUI 打断后处于空闲，与正常结束一样恢复聆听；
+                            // barge-in / 唤醒词 / 按键打断时已经重新开始聆听，无需重复
+                            if self.state == SystemState::Idle {
+                                self.resume_listening().await;
                             }
+                            return;
                         }
-                        Err(e) => {
-                            log::error!("Failed to wait for IoT fallback script: {}", e);
-                        }
+                        _ => return,
                     }
-                });
-            }
-            "tts" => {
-                if let Some(state) = &msg.state {
-                    if state == "start" || state == "sentence_start" {
-                        self.should_mute_mic = true;
+                }
+
+                match tts.state {
+                    TtsState::Start | TtsState::SentenceStart => {
+                        // 启用回声消除时麦克风保持开启，用户可以在播报时打断
+                        self.should_mute_mic = !self.config.enable_aec;
                         self.state = SystemState::Speaking;
-                        log::info!("TTS Started (state={}), muting mic for AEC, sending state 6 to GUI", state);
+                        log::info!(
+                            "TTS Started (state={}), mic {}, sending state 6 to GUI",
+                            tts.state.as_str(),
+                            if self.should_mute_mic { "muted" } else { "open (AEC)" }
+                        );
                         if let Err(e) = self.gui_bridge.send_message(r#"{"state": 6}"#).await {
                             log::error!("Failed to send state 6 to GUI: {}", e);
                         }
-                    } else if state == "stop" || state == "sentence_end" {
+                    }
+                    TtsState::Stop | TtsState::SentenceEnd => {
                         self.should_mute_mic = false;
                         self.state = SystemState::Idle;
-                        log::info!("TTS Stopped (state={}), unmuting mic, sending state 3 to GUI", state);
+                        log::info!(
+                            "TTS Stopped (state={}), unmuting mic, sending state 3 to GUI",
+                            tts.state.as_str()
+                        );
                         if let Err(e) = self.gui_bridge.send_message(r#"{"state": 3}"#).await {
                             log::error!("Failed to send state 3 to GUI: {}", e);
                         }
-                        self.send_auto_listen_command().await;
+                        self.resume_listening().await;
+                    }
+                    TtsState::Unknown => {
+                        log::warn!("Unknown TTS state: {}", text);
                     }
                 }
 
-                if let Some(t) = msg.text {
+                if let Some(t) = &tts.text {
                     log::info!("TTS: {}", t);
                     // 仅在开启TTS显示开关时才将文本发送给GUI显示
-                    if self.config.enable_tts_display {
-                        if let Err(e) = self.gui_bridge.send_message(&text).await {
-                            log::error!("Failed to send TTS text to GUI: {}", e);
-                        }
+                    if self.config.enable_tts_display
+                        && let Err(e) = self.gui_bridge.send_message(&text).await
+                    {
+                        log::error!("Failed to send TTS text to GUI: {}", e);
                     }
                 }
             }
-            "stt" => {
-                if let Some(text_content) = msg.text {
-                    log::info!("STT Result: {}", text_content);
+            ServerMessage::Stt(stt) => {
+                log::info!("STT Result: {}", stt.text);
+            }
+            ServerMessage::Llm(llm) => {
+                log::info!("LLM emotion: {:?}, text: {:?}", llm.emotion, llm.text);
+                if let Some(emotion) = llm.emotion {
+                    self.send_emotion(emotion).await;
+                }
+            }
+            ServerMessage::Mcp(_) => {
+                // 正常情况下 MCP 消息已由 NetLink 中的 MCP Gateway 处理
+                log::warn!("MCP message not handled by gateway: {}", text);
+            }
+            ServerMessage::System(system) => {
+                log::warn!("Unsupported system command: {}", system.command);
+            }
+            ServerMessage::Alert(alert) => {
+                log::warn!(
+                    "Server alert [{}]: {} (emotion={:?})",
+                    alert.status,
+                    alert.message,
+                    alert.emotion
+                );
+                if let Some(emotion) = alert.emotion {
+                    self.send_emotion(emotion).await;
+                }
+                let toast = serde_json::json!({ "type": "toast", "text": alert.message }).to_string();
+                if let Err(e) = self.gui_bridge.send_message(&toast).await {
+                    log::error!("Failed to send alert to GUI: {}", e);
+                }
+            }
+            ServerMessage::Goodbye(_) => {
+                log::info!("Server ended session");
+                self.current_session_id = None;
+                self.should_mute_mic = false;
+                self.listen_window_open = false;
+                self.state = SystemState::Idle;
+                if let Err(e) = self.gui_bridge.send_message(r#"{"state": 3}"#).await {
+                    log::error!("Failed to send to GUI: {}", e);
                 }
             }
-            other => {
-                log::warn!("Unhandled message type: {}", other);
+            ServerMessage::Unknown => {
+                log::warn!("Unhandled message type: {}", text);
             }
         }
     }
 
+    // 将表情转发给 GUI（仅在开启表情显示开关时）
+    async fn send_emotion(&self, emotion: Emotion) {
+        if !self.config.enable_emotion_display {
+            return;
+        }
+        let msg = format!(r#"{{"type":"emotion","emotion":"{}"}}"#, emotion.as_str());
+        if let Err(e) = self.gui_bridge.send_message(&msg).await {
+            log::error!("Failed to send emotion to GUI: {}", e);
+        }
+    }
+
+    // IoT 指令 Fallback
+    fn run_iot_fallback(&self, text: &str) {
+        // Fallback: 把接收到的完整 JSON 传递给外部脚本执行
+        let fallback_script = "./scripts/mcp_iot_fallback.sh";
+        let text_clone = text.to_string();
+        tokio::spawn(async move {
+            let mut child = match Command::new(fallback_script)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+            {
+                Ok(c) => c,
+                Err(e) => {
+                    log::error!("Failed to spawn IoT fallback script {}: {}", fallback_script, e);
+                    return;
+                }
+            };
+            
+            if let Some(mut stdin) = child.stdin.take() {
+                use tokio::io::AsyncWriteExt;
+                if let Err(e) = stdin.write_all(text_clone.as_bytes()).await {
+                    log::error!("Failed to write to IoT fallback script stdin: {}", e);
+                }
+            }
+            
+            match child.wait_with_output().await {
+                Ok(output) => {
+                    if !output.status.success() {
+                        let err_str = String::from_utf8_lossy(&output.stderr);
+                        log::error!("IoT fallback script failed: {}", err_str);
+                    } else {
+                        let out_str = String::from_utf8_lossy(&output.stdout);
+                        if !out_str.trim().is_empty() {
+                            log::info!("IoT fallback script output: {}", out_str);
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to wait for IoT fallback script: {}", e);
+                }
+            }
+        });
+    }
+
     // 处理来自服务器的音频数据
-    async fn process_server_audio(&mut self, data: Vec<u8>) {
+    async fn process_server_audio(&mut self, frame: AudioFrame) {
+        if self.tts_aborted {
+            return;
+        }
         if self.state != SystemState::Speaking {
             self.state = SystemState::Speaking;
             if let Err(e) = self.gui_bridge.send_message(r#"{"state": 6}"#).await {
                 log::error!("Failed to send to GUI: {}", e);
             }
         }
-        if let Err(e) = self.audio_bridge.send_audio(&data).await {
+        if let Err(e) = self.audio_bridge.send_audio(frame.payload, frame.timestamp).await {
             log::error!("Failed to send to Audio: {}", e);
         }
     }
 
-    // 发送自动监听命令
-    async fn send_auto_listen_command(&self) {
+    // 发送开始监听命令
+    async fn send_listen_start_command(&self) {
         let session_id = self.current_session_id.as_deref().unwrap_or("");
         let listen_cmd = format!(
-            r#"{{"session_id":"{}","type":"listen","state":"start","mode":"auto"}}"#,
-            session_id
+            r#"{{"session_id":"{}","type":"listen","state":"start","mode":"{}"}}"#,
+            session_id,
+            self.config.listen_mode.as_str()
         );
         if let Err(e) = self.net_tx.send(NetCommand::SendText(listen_cmd)).await {
             log::error!("Failed to send loop listen command: {}", e);
         }
     }
 
+    // 一轮播报结束后继续聆听；唤醒词 / manual 模式下保持空闲，等待下一次唤醒或按键
+    async fn resume_listening(&self) {
+        if !self.config.enable_wake_word && self.config.listen_mode != ListenMode::Manual {
+            self.send_listen_start_command().await;
+        }
+    }
+
+    // 发送停止监听命令
+    async fn send_listen_stop_command(&self) {
+        let session_id = self.current_session_id.as_deref().unwrap_or("");
+        let listen_cmd = format!(
+            r#"{{"session_id":"{}","type":"listen","state":"stop"}}"#,
+            session_id
+        );
+        if let Err(e) = self.net_tx.send(NetCommand::SendText(listen_cmd)).await {
+            log::error!("Failed to send listen stop command: {}", e);
+        }
+    }
+
+    // 发送唤醒词检测消息
+    async fn send_wake_word_detected(&self, wake_word: &str) {
+        let session_id = self.current_session_id.as_deref().unwrap_or("");
+        let detect_cmd = serde_json::json!({
+            "session_id": session_id,
+            "type": "listen",
+            "state": "detect",
+            "text": wake_word,
+        })
+        .to_string();
+        if let Err(e) = self.net_tx.send(NetCommand::SendText(detect_cmd)).await {
+            log::error!("Failed to send wake word detect command: {}", e);
+        }
+    }
+
+    // 通知服务器中止本轮 TTS，并清空本地播放队列
+    async fn abort_speaking(&mut self) {
+        let session_id = self.current_session_id.as_deref().unwrap_or("");
+        let abort_cmd = format!(r#"{{"session_id":"{}","type":"abort"}}"#, session_id);
+        if let Err(e) = self.net_tx.send(NetCommand::SendText(abort_cmd)).await {
+            log::error!("Failed to send abort command: {}", e);
+        }
+
+        self.audio_bridge.stop_playback();
+        self.tts_aborted = true;
+        self.should_mute_mic = false;
+    }
+
+    // 当前是否允许上传麦克风音频
+    fn mic_open(&self) -> bool {
+        if !self.connected || self.should_mute_mic {
+            return false;
+        }
+        if self.config.listen_mode == ListenMode::Manual {
+            return self.listen_window_open;
+        }
+        // 唤醒词模式下空闲时不上传
+        !(self.config.enable_wake_word && self.state == SystemState::Idle)
+    }
+
+    // manual 模式：打开拾音窗口并通知服务器开始聆听
+    async fn open_listen_window(&mut self) {
+        if self.state == SystemState::Speaking {
+            log::info!("Push-to-talk during TTS, aborting playback");
+            self.abort_speaking().await;
+        }
+        self.listen_window_open = true;
+        self.state = SystemState::Listening;
+        if let Err(e) = self.gui_bridge.send_message(r#"{"state": 5}"#).await {
+            log::error!("Failed to send to GUI: {}", e);
+        }
+        self.send_listen_start_command().await;
+    }
+
+    // manual 模式：关闭拾音窗口并通知服务器停止聆听
+    async fn close_listen_window(&mut self) {
+        if !self.listen_window_open {
+            return;
+        }
+        self.listen_window_open = false;
+        self.send_listen_stop_command().await;
+        self.state = SystemState::Processing;
+        if let Err(e) = self.gui_bridge.send_message(r#"{"state": 3}"#).await {
+            log::error!("Failed to send to GUI: {}", e);
+        }
+    }
+
+    // 按键（或 GUI）按下：开始聆听
+    async fn ptt_press(&mut self) {
+        if self.ptt_held {
+            return;
+        }
+        log::info!("Push-to-talk pressed");
+        self.ptt_held = true;
+        self.open_listen_window().await;
+    }
+
+    // 按键（或 GUI）松开：结束聆听
+    async fn ptt_release(&mut self) {
+        if !self.ptt_held {
+            return;
+        }
+        log::info!("Push-to-talk released");
+        self.ptt_held = false;
+        self.close_listen_window().await;
+    }
+
+    // 用户在播报时说话：中止本轮 TTS 并切换到聆听
+    async fn barge_in(&mut self) {
+        log::info!("User speech detected during TTS, aborting playback (barge-in)");
+        self.abort_speaking().await;
+        self.state = SystemState::Listening;
+        if let Err(e) = self.gui_bridge.send_message(r#"{"state": 5}"#).await {
+            log::error!("Failed to send to GUI: {}", e);
+        }
+        self.send_listen_start_command().await;
+    }
+
     // 处理来自 AudioBridge 的事件
     pub async fn handle_audio_event(&mut self, event: AudioEvent) {
         match event {
-            AudioEvent::AudioData(data) => {
-                if self.should_mute_mic {
+            AudioEvent::AudioData { data, timestamp } => {
+                if !self.mic_open() {
                     return;
                 }
-                if self.state != SystemState::Listening {
+                let frame = AudioFrame { timestamp, payload: data };
+                if let Err(e) = self.net_tx.send(NetCommand::SendBinary(frame)).await {
+                    log::error!("Failed to send audio to NetLink: {}", e);
+                }
+            }
+            AudioEvent::SpeechStart => {
+                if !self.mic_open() {
+                    return;
+                }
+                // 仅在用户真正说话时进入 Listening 并通知 GUI（播报期间由打断逻辑处理）
+                if matches!(self.state, SystemState::Idle | SystemState::Processing) {
+                    log::info!("Local VAD: speech start");
                     self.state = SystemState::Listening;
                     if let Err(e) = self.gui_bridge.send_message(r#"{"state": 5}"#).await {
                         log::error!("Failed to send to GUI: {}", e);
                     }
                 }
-                if let Err(e) = self.net_tx.send(NetCommand::SendBinary(data)).await {
-                    log::error!("Failed to send audio to NetLink: {}", e);
+            }
+            AudioEvent::SpeechEnd => {
+                if self.state != SystemState::Listening {
+                    return;
+                }
+                // 按键按住期间由松开决定结束
+                if self.ptt_held {
+                    return;
+                }
+                log::info!("Local VAD: speech end, waiting for server response");
+                if self.config.listen_mode == ListenMode::Manual {
+                    // manual 模式（如唤醒词触发）由客户端发送 listen stop
+                    self.close_listen_window().await;
+                } else {
+                    // auto / realtime 模式由服务器 VAD 判断结束
+                    self.state = SystemState::Processing;
+                    if let Err(e) = self.gui_bridge.send_message(r#"{"state": 3}"#).await {
+                        log::error!("Failed to send to GUI: {}", e);
+                    }
+                }
+            }
+            AudioEvent::BargeIn => {
+                if self.config.enable_barge_in
+                    && self.state == SystemState::Speaking
+                    && !self.tts_aborted
+                {
+                    self.barge_in().await;
+                }
+            }
+            AudioEvent::WakeWordDetected(wake_word) => {
+                match self.state {
+                    SystemState::Idle => {}
+                    SystemState::Speaking => {
+                        log::info!("Wake word during TTS, aborting playback");
+                        self.abort_speaking().await;
+                    }
+                    _ => {
+                        log::debug!("Wake word ignored in state {:?}", self.state);
+                        return;
+                    }
+                }
+                log::info!("Wake word detected: {}", wake_word);
+                self.send_wake_word_detected(&wake_word).await;
+                if self.config.listen_mode == ListenMode::Manual {
+                    self.open_listen_window().await;
+                } else {
+                    self.state = SystemState::Listening;
+                    if let Err(e) = self.gui_bridge.send_message(r#"{"state": 5}"#).await {
+                        log::error!("Failed to send to GUI: {}", e);
+                    }
+                    self.send_listen_start_command().await;
                 }
             }
         }
     }
 
+    // 处理来自按键的事件（manual 模式的按键说话）
+    pub async fn handle_key_event(&mut self, event: KeyEvent) {
+        if self.config.listen_mode != ListenMode::Manual {
+            return;
+        }
+        match event {
+            KeyEvent::Pressed => self.ptt_press().await,
+            KeyEvent::Released => self.ptt_release().await,
+        }
+    }
+
     // 处理来自 GuiBridge 的事件
     pub async fn handle_gui_event(&mut self, event: GuiEvent) {
         let GuiEvent::Message(msg) = event;
         log::info!("Received Message from GUI: {}", msg);
+
+        let parsed = serde_json::from_str::<serde_json::Value>(&msg).ok();
+        let field = |key: &str| {
+            parsed
+                .as_ref()
+                .and_then(|v| v.get(key))
+                .and_then(|t| t.as_str())
+                .map(str::to_owned)
+        };
+        let msg_type = field("type");
+
+        // manual 模式：GUI 的 listen start/stop 等同于按键按下/松开，由 Core 补全 session_id 与 mode
+        if self.config.listen_mode == ListenMode::Manual && msg_type.as_deref() == Some("listen") {
+            match field("state").as_deref() {
+                Some("start") => return self.ptt_press().await,
+                Some("stop") => return self.ptt_release().await,
+                _ => {}
+            }
+        }
+
+        // GUI 主动打断：本地立即停止播放，不等服务器停止下发
+        let is_abort = msg_type.as_deref() == Some("abort");
+        if is_abort && self.state == SystemState::Speaking {
+            log::info!("Abort requested by GUI, stopping playback");
+            self.audio_bridge.stop_playback();
+            self.tts_aborted = true;
+            self.should_mute_mic = false;
+            self.state = SystemState::Idle;
+            if let Err(e) = self.gui_bridge.send_message(r#"{"state": 3}"#).await {
+                log::error!("Failed to send to GUI: {}", e);
+            }
+        }
+
         if let Err(e) = self.net_tx.send(NetCommand::SendText(msg)).await {
             log::error!("Failed to send text to NetLink: {}", e);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net_link::SessionParams;
+    use std::borrow::Cow;
+    use std::time::Duration;
+    use tokio::net::UdpSocket;
+
+    struct Harness {
+        controller: CoreController,
+        net_rx: mpsc::Receiver<NetCommand>,
+        // 代替 GUI 进程接收 Core 发出的消息
+        gui: UdpSocket,
+    }
+
+    impl Harness {
+        async fn new(config: Config) -> Self {
+            let gui = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let config = Config {
+                gui_local_ip: Cow::Borrowed("127.0.0.1"),
+                gui_local_port: 0,
+                gui_remote_ip: Cow::Borrowed("127.0.0.1"),
+                gui_remote_port: gui.local_addr().unwrap().port(),
+                ..config
+            };
+            let (gui_tx, _) = mpsc::channel(1);
+            let gui_bridge = Arc::new(GuiBridge::new(&config, gui_tx).await.unwrap());
+            let (audio_bridge, _) = AudioBridge::detached();
+            let (net_tx, net_rx) = mpsc::channel(100);
+            let controller = CoreController::new(config, net_tx, Arc::new(audio_bridge), gui_bridge);
+            Self { controller, net_rx, gui }
+        }
+
+        async fn connect(&mut self) {
+            let session = SessionParams {
+                session_id: "session-1".to_string(),
+                transport: "websocket".to_string(),
+                audio_params: serde_json::from_str(
+                    r#"{"format":"opus","sample_rate":16000,"channels":1}"#,
+                )
+                .unwrap(),
+            };
+            self.controller.handle_net_event(NetEvent::Connected(session)).await;
+        }
+
+        async fn server_text(&mut self, text: &str) {
+            self.controller.handle_net_event(NetEvent::Text(text.to_string())).await;
+        }
+
+        // 取出目前为止发给服务器的文本消息
+        fn sent_texts(&mut self) -> Vec<serde_json::Value> {
+            let mut texts = Vec::new();
+            while let Ok(command) = self.net_rx.try_recv() {
+                if let NetCommand::SendText(text) = command {
+                    texts.push(serde_json::from_str(&text).unwrap());
+                }
+            }
+            texts
+        }
+
+        // 取出目前为止发给 GUI 的 state 值
+        async fn gui_states(&self) -> Vec<u64> {
+            let mut buf = [0u8; 1024];
+            let mut states = Vec::new();
+            let timeout = Duration::from_millis(50);
+            while let Ok(Ok(len)) = tokio::time::timeout(timeout, self.gui.recv(&mut buf)).await {
+                let msg: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+                if let Some(state) = msg["state"].as_u64() {
+                    states.push(state);
+                }
+            }
+            states
+        }
+    }
+
+    fn is_listen_start(msg: &serde_json::Value) -> bool {
+        msg["type"] == "listen" && msg["state"] == "start"
+    }
+
+    #[tokio::test]
+    async fn resumes_listening_when_aborted_round_stops() {
+        let mut h = Harness::new(Config::default()).await;
+        h.connect().await;
+        assert!(h.sent_texts().iter().any(is_listen_start));
+
+        h.server_text(r#"{"type":"tts","state":"start"}"#).await;
+        assert_eq!(h.controller.state, SystemState::Speaking);
+        let abort = r#"{"type":"abort"}"#.to_string();
+        h.controller.handle_gui_event(GuiEvent::Message(abort)).await;
+        assert_eq!(h.controller.state, SystemState::Idle);
+        assert_eq!(h.sent_texts()[0]["type"], "abort");
+        assert_eq!(h.gui_states().await.last(), Some(&3));
+
+        // 被打断的这一轮剩余的消息被丢弃，stop 到达后继续聆听
+        h.server_text(r#"{"type":"tts","state":"sentence_start","text":"..."}"#).await;
+        assert_eq!(h.controller.state, SystemState::Idle);
+        h.server_text(r#"{"type":"tts","state":"stop"}"#).await;
+        let sent = h.sent_texts();
+        assert_eq!(sent.len(), 1);
+        assert!(is_listen_start(&sent[0]));
+        assert_eq!(sent[0]["session_id"], "session-1");
+        assert!(!h.controller.tts_aborted);
+    }
+
+    #[tokio::test]
+    async fn aborted_round_stop_stays_idle_in_wake_word_mode() {
+        let config = Config { enable_wake_word: true, ..Config::default() };
+        let mut h = Harness::new(config).await;
+        h.connect().await;
+        h.server_text(r#"{"type":"tts","state":"start"}"#).await;
+        let abort = r#"{"type":"abort"}"#.to_string();
+        h.controller.handle_gui_event(GuiEvent::Message(abort)).await;
+        h.sent_texts();
+
+        h.server_text(r#"{"type":"tts","state":"stop"}"#).await;
+        assert!(h.sent_texts().is_empty());
+        assert_eq!(h.controller.state, SystemState::Idle);
+    }
+
+    #[tokio::test]
+    async fn speech_events_work_after_reconnect() {
+        let mut h = Harness::new(Config::default()).await;
+        h.connect().await;
+        let disconnected = NetEvent::Disconnected { attempt: 1, retry_in: Duration::from_secs(1) };
+        h.controller.handle_net_event(disconnected).await;
+        assert_eq!(h.controller.state, SystemState::NetworkError);
+        h.connect().await;
+        assert_eq!(h.controller.state, SystemState::Idle);
+        h.gui_states().await;
+
+        h.controller.handle_audio_event(AudioEvent::SpeechStart).await;
+        assert_eq!(h.controller.state, SystemState::Listening);
+        assert_eq!(h.gui_states().await, [5]);
+        h.controller.handle_audio_event(AudioEvent::SpeechEnd).await;
+        assert_eq!(h.controller.state, SystemState::Processing);
+    }
+}
@@ -0,0 +1,349 @@
+//! device_info - 设备信息采集
+//!
+//! 从 /proc、/sys 等 Linux 数据源采集设备信息，随 OTA / 激活请求上报给服务器，
+//! 字段名尽量与官方 ESP32 固件保持一致。读取失败的字段直接省略，
+//! config 中 device_info_exclude 列出的字段出于隐私考虑不采集
+
+use mac_address::get_mac_address;
+use serde::Serialize;
+use std::ffi::CString;
+use std::fs;
+use std::net::UdpSocket;
+use std::path::Path;
+
+/// 可在 device_info_exclude 中排除的字段
+pub const FIELDS: &[&str] = &[
+    "mac_address",
+    "chip_info",
+    "memory",
+    "flash_size",
+    "partition_table",
+    "kernel_version",
+    "network",
+    "uptime",
+];
+
+#[derive(Debug, Default, Serialize)]
+pub struct DeviceInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chip_model_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chip_info: Option<ChipInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryInfo>,
+    /// 根文件系统容量（字节）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flash_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition_table: Option<Vec<Partition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChipInfo {
+    pub model: String,
+    pub cores: usize,
+}
+
+/// 内存信息（字节）
+#[derive(Debug, Serialize)]
+pub struct MemoryInfo {
+    pub total: u64,
+    pub available: u64,
+}
+
+/// 块设备分区，来自 /proc/partitions
+#[derive(Debug, Serialize)]
+pub struct Partition {
+    pub label: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NetworkInfo {
+    /// "wifi" 或 "ethernet"
+    #[serde(rename = "type")]
+    pub network_type: String,
+    pub interface: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rssi: Option<i32>,
+}
+
+impl DeviceInfo {
+    /// 采集设备信息，exclude 中的字段不采集
+    pub fn collect(exclude: &[String]) -> Self {
+        let enabled = |field: &str| !exclude.iter().any(|f| f == field);
+        let mut info = Self::default();
+
+        if enabled("mac_address") {
+            info.mac_address = get_mac_address()
+                .ok()
+                .flatten()
+                .map(|mac| mac.to_string().to_lowercase());
+        }
+        if enabled("chip_info") {
+            info.chip_info = read_chip_info();
+            info.chip_model_name = info.chip_info.as_ref().map(|chip| chip.model.clone());
+        }
+        if enabled("memory") {
+            info.memory = read_memory();
+        }
+        if enabled("flash_size") {
+            info.flash_size = disk_size("/");
+        }
+        if enabled("partition_table") {
+            info.partition_table = read_partitions();
+        }
+        if enabled("kernel_version") {
+            info.kernel_version = fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|v| v.trim().to_string());
+        }
+        if enabled("network") {
+            info.network = read_network();
+        }
+        if enabled("uptime") {
+            info.uptime_secs = fs::read_to_string("/proc/uptime")
+                .ok()
+                .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+                .map(|secs| secs as u64);
+        }
+        info
+    }
+}
+
+fn read_chip_info() -> Option<ChipInfo> {
+    Some(parse_chip_info(&fs::read_to_string("/proc/cpuinfo").ok()?))
+}
+
+// x86 上为 "model name"，ARM 上通常只有 "Hardware" 或 "Model"
+fn parse_chip_info(cpuinfo: &str) -> ChipInfo {
+    let value = |key: &str| {
+        cpuinfo.lines().find_map(|line| {
+            let (k, v) = line.split_once(':')?;
+            (k.trim() == key).then(|| v.trim().to_string())
+        })
+    };
+    let model = value("model name")
+        .or_else(|| value("Model"))
+        .or_else(|| value("Hardware"))
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string());
+    let cores = cpuinfo
+        .lines()
+        .filter(|line| line.starts_with("processor"))
+        .count()
+        .max(1);
+    ChipInfo { model, cores }
+}
+
+fn read_memory() -> Option<MemoryInfo> {
+    parse_memory(&fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+// 数值单位为 kB；旧内核没有 MemAvailable 时退回 MemFree
+fn parse_memory(meminfo: &str) -> Option<MemoryInfo> {
+    let value = |key: &str| {
+        meminfo.lines().find_map(|line| {
+            let rest = line.strip_prefix(key)?.strip_prefix(':')?;
+            rest.split_whitespace().next()?.parse::<u64>().ok()
+        })
+    };
+    Some(MemoryInfo {
+        total: value("MemTotal")? * 1024,
+        available: value("MemAvailable").or_else(|| value("MemFree"))? * 1024,
+    })
+}
+
+fn disk_size(path: &str) -> Option<u64> {
+    let path = CString::new(path).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path 是合法的 C 字符串，stat 为可写的 statvfs 结构体
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_blocks as u64 * stat.f_frsize as u64)
+}
+
+fn read_partitions() -> Option<Vec<Partition>> {
+    Some(parse_partitions(&fs::read_to_string("/proc/partitions").ok()?))
+}
+
+// /proc/partitions 中的块数以 1KiB 为单位
+fn parse_partitions(partitions: &str) -> Vec<Partition> {
+    partitions
+        .lines()
+        .skip(2)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let blocks = fields.get(2)?.parse::<u64>().ok()?;
+            let label = fields.get(3)?;
+            // 忽略 loop / ram 等虚拟块设备
+            if label.starts_with("loop") || label.starts_with("ram") {
+                return None;
+            }
+            Some(Partition {
+                label: label.to_string(),
+                size: blocks * 1024,
+            })
+        })
+        .collect()
+}
+
+// 默认路由所在网卡及本机访问外网时使用的地址
+fn read_network() -> Option<NetworkInfo> {
+    let interface = default_route_interface()?;
+    // UDP connect 只做路由选择，不会发出数据包
+    let ip = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("8.8.8.8:80")?;
+            socket.local_addr()
+        })
+        .ok()
+        .map(|addr| addr.ip().to_string());
+
+    let wireless = Path::new("/sys/class/net").join(&interface).join("wireless").exists();
+    let rssi = if wireless { read_rssi(&interface) } else { None };
+    Some(NetworkInfo {
+        network_type: if wireless { "wifi" } else { "ethernet" }.to_string(),
+        interface,
+        ip,
+        rssi,
+    })
+}
+
+fn default_route_interface() -> Option<String> {
+    parse_default_route_interface(&fs::read_to_string("/proc/net/route").ok()?)
+}
+
+// /proc/net/route 中目的地址为 00000000 的是默认路由
+fn parse_default_route_interface(route: &str) -> Option<String> {
+    route.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let interface = fields.next()?;
+        (fields.next()? == "00000000").then(|| interface.to_string())
+    })
+}
+
+fn read_rssi(interface: &str) -> Option<i32> {
+    parse_rssi(&fs::read_to_string("/proc/net/wireless").ok()?, interface)
+}
+
+// /proc/net/wireless: "wlan0: 0000   54.  -56.  -256 ..."，第 4 列为信号强度（dBm）
+fn parse_rssi(wireless: &str, interface: &str) -> Option<i32> {
+    wireless.lines().find_map(|line| {
+        let (name, rest) = line.split_once(':')?;
+        if name.trim() != interface {
+            return None;
+        }
+        let level = rest.split_whitespace().nth(2)?;
+        level.trim_end_matches('.').parse::<f32>().ok().map(|v| v as i32)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPUINFO_X86: &str = "\
+processor\t: 0
+vendor_id\t: GenuineIntel
+model name\t: Intel(R) Core(TM) i5-8250U CPU @ 1.60GHz
+cpu cores\t: 2
+
+processor\t: 1
+vendor_id\t: GenuineIntel
+model name\t: Intel(R) Core(TM) i5-8250U CPU @ 1.60GHz
+cpu cores\t: 2
+";
+
+    // 树莓派 4：没有 model name，型号在末尾的 Model 中
+    const CPUINFO_ARM: &str = "\
+processor\t: 0
+BogoMIPS\t: 108.00
+CPU implementer\t: 0x41
+CPU part\t: 0xd08
+
+processor\t: 1
+BogoMIPS\t: 108.00
+CPU implementer\t: 0x41
+CPU part\t: 0xd08
+
+Hardware\t: BCM2835
+Revision\t: c03114
+Model\t\t: Raspberry Pi 4 Model B Rev 1.4
+";
+
+    #[test]
+    fn parses_x86_cpuinfo() {
+        let chip = parse_chip_info(CPUINFO_X86);
+        assert_eq!(chip.model, "Intel(R) Core(TM) i5-8250U CPU @ 1.60GHz");
+        assert_eq!(chip.cores, 2);
+    }
+
+    #[test]
+    fn parses_arm_cpuinfo() {
+        let chip = parse_chip_info(CPUINFO_ARM);
+        assert_eq!(chip.model, "Raspberry Pi 4 Model B Rev 1.4");
+        assert_eq!(chip.cores, 2);
+
+        // 较老的 ARM 内核只有 Hardware
+        let chip = parse_chip_info("processor\t: 0\nHardware\t: Allwinner sun8i Family\n");
+        assert_eq!((chip.model.as_str(), chip.cores), ("Allwinner sun8i Family", 1));
+    }
+
+    #[test]
+    fn parses_meminfo() {
+        let memory = parse_memory(
+            "MemTotal:        3884096 kB\nMemFree:          245812 kB\nMemAvailable:    2936380 kB\n",
+        )
+        .unwrap();
+        assert_eq!((memory.total, memory.available), (3884096 * 1024, 2936380 * 1024));
+
+        // 没有 MemAvailable 时使用 MemFree
+        let memory = parse_memory("MemTotal:  250000 kB\nMemFree:  120000 kB\n").unwrap();
+        assert_eq!(memory.available, 120000 * 1024);
+        assert!(parse_memory("MemFree:  120000 kB\n").is_none());
+    }
+
+    #[test]
+    fn parses_partitions_without_virtual_devices() {
+        let partitions = parse_partitions(
+            "major minor  #blocks  name\n\n   1        0       4096 ram0\n   7        0      56820 loop0\n 179        0   31166976 mmcblk0\n 179        1     262144 mmcblk0p1\n",
+        );
+        let list: Vec<_> = partitions.iter().map(|p| (p.label.as_str(), p.size)).collect();
+        assert_eq!(list, [("mmcblk0", 31166976 * 1024), ("mmcblk0p1", 262144 * 1024)]);
+    }
+
+    #[test]
+    fn finds_default_route_interface() {
+        let route = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t0001A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+";
+        assert_eq!(parse_default_route_interface(route).as_deref(), Some("wlan0"));
+        // 只有局域网路由时没有默认网卡
+        let (local_only, _) = route.split_at(route.find("wlan0\t00000000").unwrap());
+        assert!(parse_default_route_interface(local_only).is_none());
+    }
+
+    #[test]
+    fn parses_rssi_of_interface() {
+        let wireless = "\
+Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+ wlan0: 0000   54.  -56.  -256        0      0      0      0     12        0
+";
+        assert_eq!(parse_rssi(wireless, "wlan0"), Some(-56));
+        assert_eq!(parse_rssi(wireless, "wlan1"), None);
+    }
+}
@@ -35,13 +35,12 @@ impl GuiBridge {
         loop {
             // 通过UDP socket接收消息
             let (len, _) = self.socket.recv_from(&mut buf).await?;
-            if len > 0 {
-                if let Ok(msg) = std::str::from_utf8(&buf[..len]) {
-                    if let Err(e) = self.tx.send(GuiEvent::Message(msg.to_string())).await {
-                        log::error!("Failed to send GUI event: {}", e);
-                        break;
-                    }
-                }
+            if len > 0
+                && let Ok(msg) = std::str::from_utf8(&buf[..len])
+                && let Err(e) = self.tx.send(GuiEvent::Message(msg.to_string())).await
+            {
+                log::error!("Failed to send GUI event: {}", e);
+                break;
             }
         }
         Ok(())
@@ -0,0 +1,74 @@
+use crate::config::Config;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+
+// linux/input-event-codes.h
+const EV_KEY: u16 = 0x01;
+
+// struct input_event { struct timeval time; __u16 type; __u16 code; __s32 value; }
+// timeval 由两个 long 组成，因此 32 位板子上是 16 字节，64 位上是 24 字节
+const TIMEVAL_SIZE: usize = 2 * std::mem::size_of::<usize>();
+const INPUT_EVENT_SIZE: usize = TIMEVAL_SIZE + 8;
+
+pub enum KeyEvent {
+    Pressed,
+    Released,
+}
+
+// 通过 evdev 读取按键（GPIO 按键经 gpio-keys 驱动后同样表现为 /dev/input/eventX）
+pub struct KeyInput {
+    device: String,
+    key_code: u16,
+    tx: mpsc::Sender<KeyEvent>,
+}
+
+impl KeyInput {
+    pub fn new(config: &Config, tx: mpsc::Sender<KeyEvent>) -> Self {
+        Self {
+            device: config.ptt_input_device.to_string(),
+            key_code: config.ptt_key_code,
+            tx,
+        }
+    }
+
+    pub async fn run(&self) -> anyhow::Result<()> {
+        let mut file = File::open(&self.device)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to open input device {}: {}", self.device, e))?;
+        log::info!(
+            "Push-to-talk key input started: device={}, key_code={}",
+            self.device,
+            self.key_code
+        );
+
+        let mut buf = [0u8; INPUT_EVENT_SIZE];
+        loop {
+            file.read_exact(&mut buf).await?;
+
+            let ev_type = u16::from_ne_bytes([buf[TIMEVAL_SIZE], buf[TIMEVAL_SIZE + 1]]);
+            let code = u16::from_ne_bytes([buf[TIMEVAL_SIZE + 2], buf[TIMEVAL_SIZE + 3]]);
+            let value = i32::from_ne_bytes([
+                buf[TIMEVAL_SIZE + 4],
+                buf[TIMEVAL_SIZE + 5],
+                buf[TIMEVAL_SIZE + 6],
+                buf[TIMEVAL_SIZE + 7],
+            ]);
+
+            if ev_type != EV_KEY || code != self.key_code {
+                continue;
+            }
+
+            // value: 1 按下, 0 松开, 2 长按自动重复（忽略）
+            let event = match value {
+                1 => KeyEvent::Pressed,
+                0 => KeyEvent::Released,
+                _ => continue,
+            };
+            if self.tx.send(event).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
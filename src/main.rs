@@ -1,22 +1,35 @@
 mod activation;
 mod audio;
 mod audio_bridge;
+mod clock;
 mod config;
 mod controller;
+mod device_info;
 mod gui_bridge;
+mod key_input;
 mod mcp_gateway;
 mod net_link;
+mod ota;
+mod outbound;
 mod protocol;
 mod state_machine;
+#[cfg(test)]
+mod test_server;
+mod transport;
 
+use audio::PromptClips;
 use audio_bridge::{AudioBridge, AudioEvent};
 use config::Config;
 use controller::CoreController;
 use gui_bridge::{GuiBridge, GuiEvent};
+use key_input::{KeyEvent, KeyInput};
 
 use mac_address::get_mac_address;
 use net_link::{NetCommand, NetEvent, NetLink};
+use std::future::Future;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::signal;
 use tokio::sync::mpsc;
 use uuid::Uuid;
@@ -38,6 +51,14 @@ async fn main() -> anyhow::Result<()> {
         })
         .init();
 
+    // 新版本连续启动失败时回滚到旧版本，退出后由守护进程重启
+    // 放在加载配置之前，新版本因配置不兼容而启动失败时同样计入启动次数
+    match ota::check_pending_boot() {
+        Ok(true) => std::process::exit(ota::EXIT_CODE_UPGRADED),
+        Ok(false) => {}
+        Err(e) => log::error!("OTA boot verification failed: {:#}", e),
+    }
+
     // 加载配置（若不存在则根据编译时默认生成并持久化）
     let mut config = Config::load_or_create()?;
 
@@ -47,14 +68,33 @@ async fn main() -> anyhow::Result<()> {
         std::process::exit(1);
     }
 
+    // OTA / 激活请求的 HTTP 客户端（代理、私有 CA、客户端证书）
+    // 时钟校准前会容忍尚未生效的证书，只用于 OTA 接口和固件下载
+    let ota_client = match outbound::ota_http_client(&config.outbound) {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("🛑 程序启动失败：{:#}", e);
+            std::process::exit(1);
+        }
+    };
+
     // 设备id和客户端id的处理
     let mut config_dirty = false;
+    // device_id 会作为 Device-Id 请求头发送，排除 mac_address 时不能用 MAC 生成
+    let hide_mac = config.device_info_exclude.iter().any(|f| f == "mac_address");
+    let mac = get_mac_address().ok().flatten().map(|mac| mac.to_string().to_lowercase());
     if config.device_id == "unknown-device" {
-        config.device_id = match get_mac_address() {
-            Ok(Some(mac)) => mac.to_string().to_lowercase(),
+        config.device_id = match mac {
+            Some(mac) if !hide_mac => mac,
             _ => Uuid::new_v4().to_string(),
         };
         config_dirty = true;
+    } else if hide_mac && mac.as_deref() == Some(config.device_id.as_str()) {
+        // 已激活的设备改 device_id 需要重新激活，只提示不自动修改
+        log::warn!(
+            "device_info excludes mac_address but device_id is still the MAC; \
+             set device_id to \"unknown-device\" and re-activate to stop sending it"
+        );
     }
 
     if config.client_id == "unknown-client" {
@@ -63,23 +103,10 @@ async fn main() -> anyhow::Result<()> {
         config_dirty = true;
     }
 
-    if config_dirty {
-        if let Err(e) = config.save() {
-            log::error!("Failed to persist updated config: {}", e);
-        }
+    if config_dirty && let Err(e) = config.save() {
+        log::error!("Failed to persist updated config: {}", e);
     }
 
-    // 初始化 MCP Gateway 工具箱
-    let mcp_configs = if config.mcp.enabled {
-        log::info!("MCP Gateway is enabled. Loaded {} tools from configuration.", config.mcp.tools.len());
-        config.mcp.tools.clone()
-    } else {
-        log::info!("MCP Gateway is disabled.");
-        vec![]
-    };
-
-    let mcp_server = Arc::new(init_mcp_gateway(mcp_configs));
-
     // 创建通道，用于组件间通信
     // 事件通道
     let (tx_net_event, mut rx_net_event) = mpsc::channel::<NetEvent>(100);
@@ -93,6 +120,9 @@ async fn main() -> anyhow::Result<()> {
     // GUI进程通道
     let (tx_gui_event, mut rx_gui_event) = mpsc::channel::<GuiEvent>(100);
 
+    // 按键通道（manual 拾音模式）
+    let (tx_key_event, mut rx_key_event) = mpsc::channel::<KeyEvent>(16);
+
     // 启动GUI桥，与GUI进程通信，优先启动，用于播报激活状态或者激活码
     let gui_bridge = Arc::new(GuiBridge::new(&config, tx_gui_event).await?);
     // clone一份，用于异步任务，还要用原始的gui_bridge在主循环中发送消息
@@ -103,17 +133,77 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
+    // 启动音频桥（内置音频系统，无需外部进程），在激活前启动以便播报验证码
+    let audio_bridge = Arc::new(AudioBridge::start(&config, tx_audio_event)?);
+
+    // 播报验证码的预录制语音（或显式开启的 DTMF 后备提示音），加载失败时验证码只在 GUI 上显示
+    let activation_prompts = if config.activation_code_prompt_interval_secs > 0 {
+        match PromptClips::load(
+            Path::new(config.voice_prompt_dir.as_ref()),
+            config.voice_prompt_tone_fallback,
+        ) {
+            Ok(clips) => Some(clips),
+            Err(e) => {
+                log::warn!("Activation code prompts disabled: {:#}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let prompt_interval = Duration::from_secs(config.activation_code_prompt_interval_secs as u64);
+    let mut activation_code: Option<String> = None;
+    let mut last_prompt: Option<Instant> = None;
+
     // 在启动 NetLink 前检查激活
+    let mut activation = match activation::Activation::new(&config, &ota_client) {
+        Ok(activation) => activation,
+        Err(e) => {
+            log::error!("🛑 程序启动失败：{:#}", e);
+            std::process::exit(1);
+        }
+    };
     loop {
-        match activation::check_device_activation(&config).await {
-            activation::ActivationResult::Activated => {
-                log::info!("Device is activated. Starting WebSocket...");
+        match discard_audio_events(&mut rx_audio_event, activation.step()).await {
+            activation::ActivationResult::Activated(response) => {
+                if let Some(mqtt) = response.mqtt {
+                    log::info!("OTA 下发 MQTT 配置: endpoint={}", mqtt.endpoint);
+                    config.mqtt = mqtt;
+                }
+                // 服务器下发的 WebSocket 地址和 token 覆盖出厂配置并持久化
+                if let Some(websocket) = response.websocket
+                    && websocket.apply(&mut config)
+                {
+                    log::info!("OTA 下发 WebSocket 配置: url={}", config.ws_url);
+                    if let Err(e) = config.save() {
+                        log::error!("Failed to persist updated config: {}", e);
+                    }
+                }
+                // 有新版本时替换程序并退出，由守护进程重启为新版本
+                if config.enable_ota_upgrade
+                    && let Some(firmware) = &response.firmware
+                {
+                    match ota::upgrade_if_newer(&ota_client, firmware).await {
+                        Ok(true) => {
+                            log::info!("OTA 升级完成，退出等待重启");
+                            std::process::exit(ota::EXIT_CODE_UPGRADED);
+                        }
+                        Ok(false) => {}
+                        Err(e) => log::error!("OTA upgrade failed: {:#}", e),
+                    }
+                }
+                log::info!(
+                    "Device is activated. Starting network link (transport: {})...",
+                    config.transport.as_str()
+                );
                 if let Err(e) = gui_bridge
                     .send_message(r#"{"type":"toast", "text":"设备已激活"}"#)
                     .await
                 {
                     log::error!("Failed to send GUI message: {}", e);
                 }
+                // 停止尚未播完的验证码播报
+                audio_bridge.stop_playback();
                 break; // 跳出循环，继续下面的 NetLink 启动
             }
             activation::ActivationResult::NeedActivation(code) => {
@@ -125,28 +215,72 @@ async fn main() -> anyhow::Result<()> {
                     log::error!("Failed to send GUI message: {}", e);
                 }
 
-                // TTS 播报
-                // 如果支持的话，可以设置在这里
-                // audio_bridge.speak_text(format!("请在手机输入验证码 {}", code)).await;
-
-                // 等待几秒再轮询
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                // 验证码变化时立即重新播报
+                if activation_code.as_deref() != Some(code.as_str()) {
+                    activation_code = Some(code);
+                    last_prompt = None;
+                }
+            }
+            activation::ActivationResult::Pending => {
+                log::info!("Waiting for the activation code to be entered...");
             }
             activation::ActivationResult::Error(e) => {
-                log::error!("Activation check error: {}. Retrying in 5s...", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                log::error!(
+                    "Activation check error: {}. Retrying in {}s...",
+                    e,
+                    activation.retry_delay().as_secs()
+                );
+            }
+        }
+
+        // 每个轮询周期检查一次，距上次播报超过间隔时用本地提示音播报验证码
+        if let (Some(clips), Some(code)) = (&activation_prompts, &activation_code)
+            && last_prompt.is_none_or(|t| t.elapsed() >= prompt_interval)
+        {
+            audio_bridge.stop_playback();
+            if let Err(e) = audio_bridge.play_prompts(&clips.activation_code(code)).await {
+                log::error!("Failed to play activation code prompt: {}", e);
             }
+            last_prompt = Some(Instant::now());
         }
+
+        // 等待几秒再轮询
+        discard_audio_events(&mut rx_audio_event, tokio::time::sleep(activation.retry_delay())).await;
     }
 
+    // 初始化 MCP Gateway 工具箱
+    // 激活完成后时钟已用服务器时间校准，MCP HTTP 工具严格校验证书有效期
+    let mcp_configs = if config.mcp.enabled {
+        log::info!("MCP Gateway is enabled. Loaded {} tools from configuration.", config.mcp.tools.len());
+        config.mcp.tools.clone()
+    } else {
+        log::info!("MCP Gateway is disabled.");
+        vec![]
+    };
+    let http_client = match outbound::http_client(&config.outbound) {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("🛑 程序启动失败：{:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let mcp_server = Arc::new(init_mcp_gateway(mcp_configs, http_client));
+
     // 启动网络链接，与小智服务器通信
     let net_link = NetLink::new(config.clone(), tx_net_event, rx_net_cmd, mcp_server);
     tokio::spawn(async move {
         net_link.run().await;
     });
 
-    // 启动音频桥（内置音频系统，无需外部进程）
-    let audio_bridge = Arc::new(AudioBridge::start(&config, tx_audio_event)?);
+    // manual 拾音模式下启动按键监听
+    if config.listen_mode == config::ListenMode::Manual && !config.ptt_input_device.is_empty() {
+        let key_input = KeyInput::new(&config, tx_key_event);
+        tokio::spawn(async move {
+            if let Err(e) = key_input.run().await {
+                log::error!("KeyInput error: {}", e);
+            }
+        });
+    }
 
     // 初始化控制器
     let mut controller = CoreController::new(
@@ -167,7 +301,19 @@ async fn main() -> anyhow::Result<()> {
             Some(event) = rx_net_event.recv() => controller.handle_net_event(event).await,
             Some(event) = rx_audio_event.recv() => controller.handle_audio_event(event).await,
             Some(event) = rx_gui_event.recv() => controller.handle_gui_event(event).await,
+            Some(event) = rx_key_event.recv() => controller.handle_key_event(event).await,
         }
     }
     Ok(())
 }
+
+// 等待 fut 完成，期间丢弃录音事件：激活阶段还没有会话，避免通道堆积阻塞录音线程
+async fn discard_audio_events<F: Future>(rx: &mut mpsc::Receiver<AudioEvent>, fut: F) -> F::Output {
+    tokio::pin!(fut);
+    loop {
+        tokio::select! {
+            output = &mut fut => return output,
+            Some(_) = rx.recv() => {}
+        }
+    }
+}
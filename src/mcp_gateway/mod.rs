@@ -8,11 +8,11 @@ pub use server::McpServer;
 
 use tool::DynamicTool;
 
-pub fn init_mcp_gateway(configs: Vec<ExternalToolConfig>) -> McpServer {
+pub fn init_mcp_gateway(configs: Vec<ExternalToolConfig>, http_client: reqwest::Client) -> McpServer {
     let mut server = McpServer::new();
     for config in configs {
         let tool_name = config.name.clone();
-        let tool = DynamicTool::new(config);
+        let tool = DynamicTool::new(config, http_client.clone());
         server.register_tool(Box::new(tool));
         log::info!("Registered MCP Tool: {}", tool_name);
     }
@@ -17,21 +17,27 @@ pub trait McpTool: Send + Sync {
 
 pub struct DynamicTool {
     config: ExternalToolConfig,
+    // 按 [outbound] 配置代理和证书的 HTTP 客户端
+    http_client: reqwest::Client,
 }
 
 impl DynamicTool {
-    pub fn new(config: ExternalToolConfig) -> Self {
-        Self { config }
+    pub fn new(config: ExternalToolConfig, http_client: reqwest::Client) -> Self {
+        Self { config, http_client }
     }
 
     /// 根据传输协议类型分发执行（纯异步非阻塞）
-    async fn execute_inner(config: &ExternalToolConfig, params: Value) -> Result<Value, String> {
+    async fn execute_inner(
+        config: &ExternalToolConfig,
+        http_client: &reqwest::Client,
+        params: Value,
+    ) -> Result<Value, String> {
         match &config.transport {
             ToolTransport::Subprocess { executable, args } => {
                 Self::exec_subprocess(executable, args, params).await
             }
             ToolTransport::Http { url, method } => {
-                Self::exec_http(url, method, params).await
+                Self::exec_http(http_client, url, method, params).await
             }
             ToolTransport::Tcp { address } => {
                 Self::exec_tcp(address, params).await
@@ -75,9 +81,12 @@ impl DynamicTool {
     }
 
     /// HTTP 调用（reqwest 异步非阻塞）
-    async fn exec_http(url: &str, method: &str, params: Value) -> Result<Value, String> {
-        let client = reqwest::Client::new();
-
+    async fn exec_http(
+        client: &reqwest::Client,
+        url: &str,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, String> {
         let request = match method.to_uppercase().as_str() {
             "GET" => client.get(url),
             _ => client.post(url).json(&params),
@@ -142,6 +151,7 @@ impl McpTool for DynamicTool {
         // ---- 后台模式（对话级异步） ----
         if self.config.mode == ExecutionMode::Background {
             let config_clone = self.config.clone();
+            let http_client = self.http_client.clone();
             let timeout_ms = self.config.timeout_ms;
 
             tokio::spawn(async move {
@@ -150,7 +160,7 @@ impl McpTool for DynamicTool {
 
                 let _result = match timeout(
                     timeout_duration,
-                    Self::execute_inner(&config_clone, params),
+                    Self::execute_inner(&config_clone, &http_client, params),
                 )
                 .await
                 {
@@ -162,7 +172,7 @@ impl McpTool for DynamicTool {
                                 "text": msg
                             }]
                         });
-                        log::info!("✓ 后台任务 [{}] 执行完成 | MCP输出: {}", config_clone.name, mcp_output.to_string());
+                        log::info!("✓ 后台任务 [{}] 执行完成 | MCP输出: {}", config_clone.name, mcp_output);
                         log::info!("✓ 后台任务 [{}] 执行完成 | 脚本输出: {}", config_clone.name, msg);
                         Ok(msg)
                     }
@@ -197,7 +207,7 @@ impl McpTool for DynamicTool {
         let timeout_duration = Duration::from_millis(self.config.timeout_ms);
         let config = &self.config;
 
-        match timeout(timeout_duration, Self::execute_inner(config, params)).await {
+        match timeout(timeout_duration, Self::execute_inner(config, &self.http_client, params)).await {
             Ok(Ok(result)) => Ok(result),
             Ok(Err(err)) => Err(err),
             Err(_) => Err(format!(
@@ -1,55 +1,87 @@
-use crate::config::Config;
-use futures_util::{SinkExt, StreamExt};
-use mac_address::get_mac_address;
-use serde::Serialize;
-use serde_json::{json, Value};
-use tokio::sync::mpsc;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-use url::Url;
-use uuid::Uuid;
-use std::sync::Arc;
+use crate::config::{Config, TransportType};
 use crate::mcp_gateway::McpServer;
+use crate::protocol::AudioParams;
+use crate::transport::{MqttTransport, Transport, WebSocketTransport};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 一帧音频及其时间戳（毫秒）
+/// 下行帧的时间戳来自服务器；上行帧携带录音时正在播放的下行帧时间戳，供服务器端回声消除对齐
+/// 协议不携带时间戳时为 0
+#[derive(Debug)]
+pub struct AudioFrame {
+    pub timestamp: u32,
+    pub payload: Vec<u8>,
+}
+
+/// 与服务器完成 hello 握手后协商得到的会话参数
+#[derive(Debug, Clone)]
+pub struct SessionParams {
+    pub session_id: String,
+    pub transport: String,
+    /// 服务器下发 TTS 音频的格式
+    pub audio_params: AudioParams,
+}
 
 #[derive(Debug)]
 pub enum NetEvent {
     Text(String),
-    Binary(Vec<u8>),
-    Connected,
-    Disconnected,
+    Binary(AudioFrame),
+    // 服务器 hello 校验通过后才发出
+    Connected(SessionParams),
+    // 连接断开，retry_in 后发起第 attempt 次重连
+    Disconnected { attempt: u32, retry_in: Duration },
+    // 心跳 Ping 到 Pong 的往返时延
+    Rtt(Duration),
 }
 
 #[derive(Debug)]
 pub enum NetCommand {
     SendText(String),
-    SendBinary(Vec<u8>),
+    SendBinary(AudioFrame),
 }
 
-// 音频参数结构体
-#[derive(Serialize)]
-struct AudioParams {
-    format: String,
-    sample_rate: u32,
-    channels: u8,
-    frame_duration: u32,
+/// 断线重连的退避策略
+/// 等待时间从 min 起每次翻倍直到 max，实际等待取 [base/2, base] 间的随机值，
+/// 避免大量设备在服务端故障恢复后同时重连；连接稳定超过 stable 后重置
+struct Backoff {
+    min: Duration,
+    max: Duration,
+    stable: Duration,
+    base: Duration,
+    attempt: u32,
 }
 
-// Features 声明结构体，用于告知服务端设备支持的能力
-#[derive(Serialize)]
-struct Features {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    mcp: Option<bool>,
-}
+impl Backoff {
+    fn new(config: &Config) -> Self {
+        let min = Duration::from_secs(config.reconnect_min_secs as u64);
+        Self {
+            min,
+            max: Duration::from_secs(config.reconnect_max_secs as u64),
+            stable: Duration::from_secs(config.reconnect_stable_secs as u64),
+            base: min,
+            attempt: 0,
+        }
+    }
+
+    /// 计算下一次重连的等待时间，uptime 为刚断开的连接保持的时长
+    fn next_delay(&mut self, uptime: Option<Duration>) -> Duration {
+        if let Some(uptime) = uptime
+            && uptime >= self.stable
+        {
+            log::info!("Connection was stable for {}s, backoff reset", uptime.as_secs());
+            self.base = self.min;
+            self.attempt = 0;
+        }
+
+        let base = self.base;
+        self.base = std::cmp::min(self.base * 2, self.max);
+        self.attempt += 1;
 
-// Hello Message，用于初始化连接
-#[derive(Serialize)]
-struct HelloMessage {
-    #[serde(rename = "type")]
-    msg_type: String,
-    version: u8,
-    transport: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    features: Option<Features>,
-    audio_params: AudioParams,
+        let jittered = rand::random_range(base.as_millis() as u64 / 2..=base.as_millis() as u64);
+        std::cmp::max(Duration::from_millis(jittered), self.min)
+    }
 }
 
 pub struct NetLink {
@@ -69,172 +101,103 @@ impl NetLink {
         Self { config, tx, rx_cmd, mcp_server }
     }
 
-    // 如果发生错误断开连接，5秒后重连
+    // 如果发生错误断开连接，按退避策略等待后重连
     pub async fn run(mut self) {
-        // 重试机制，指数退避
-        let mut retry_delay = 1;
-        loop {
-            if let Err(e) = self.connect_and_loop().await {
-                log::error!("Connection error: {}. Retrying in {}s...", e, retry_delay);
-                let _ = self.tx.send(NetEvent::Disconnected).await;
-                tokio::time::sleep(tokio::time::Duration::from_secs(retry_delay)).await;
-                retry_delay = std::cmp::min(retry_delay * 2, 60);
-            } else {
-                // 如果连接和主循环正常退出，重置重试延迟
-                // If it returns Ok, it might mean clean exit or just a disconnect that wasn't caught as Err?
-                // In our case, connect_and_loop returns Err on disconnect.
-                // If it returns Ok, it means we are shutting down (rx_cmd closed).
-                break;
-            }
+        let mut transport = self.create_transport();
+        log::info!("Using {} transport", transport.name());
+
+        let mut backoff = Backoff::new(&self.config);
+        // Transport::run 在断开时返回 Err；返回 Ok 说明 rx_cmd 已关闭，程序正在退出
+        while let Err(e) = transport.run(&self.tx, &mut self.rx_cmd).await {
+            let uptime = transport.connected_at().map(|t| t.elapsed());
+            let retry_in = backoff.next_delay(uptime);
+            log::error!(
+                "Connection error: {}. Retrying in {:.1}s (attempt {})...",
+                e,
+                retry_in.as_secs_f32(),
+                backoff.attempt
+            );
+            let _ = self
+                .tx
+                .send(NetEvent::Disconnected {
+                    attempt: backoff.attempt,
+                    retry_in,
+                })
+                .await;
+            tokio::time::sleep(retry_in).await;
         }
     }
 
-    // 进入连接和主循环，处理WebSocket消息和发送命令
-    async fn connect_and_loop(&mut self) -> anyhow::Result<()> {
-        // 如果设备ID是unknown-device，则尝试获取MAC地址作为设备ID
-        let device_id = if self.config.device_id == "unknown-device" {
-            match get_mac_address() {
-                Ok(Some(mac)) => mac.to_string().to_lowercase(), // Ensure lowercase to match typical Linux behavior 注意大小写一致，以匹配典型的Linux行为
-                _ => Uuid::new_v4().to_string(), // 如果无法获取MAC地址，则生成新的UUID
+    // 根据配置选择传输协议；auto 模式下 OTA 下发了 MQTT 配置时使用 MQTT
+    // MQTT 不支持 socks5 代理，auto 模式下配置了 socks5 代理时仍使用 WebSocket
+    fn create_transport(&self) -> Box<dyn Transport> {
+        let use_mqtt = match self.config.transport {
+            TransportType::WebSocket => false,
+            TransportType::Mqtt => true,
+            TransportType::Auto => {
+                let socks5 = self.config.outbound.proxy.trim().starts_with("socks5://");
+                if socks5 && self.config.mqtt.is_configured() {
+                    log::warn!("MQTT does not support socks5 proxy, falling back to WebSocket");
+                }
+                self.config.mqtt.is_configured() && !socks5
             }
-        } else {
-            self.config.device_id.clone() // 使用配置中的设备ID
         };
-
-        // 根据配置构建WebSocket请求
-        let url = Url::parse(self.config.ws_url.as_ref())?;
-        let host = url.host_str().unwrap_or("api.tenclass.net");
-
-        let request = tokio_tungstenite::tungstenite::http::Request::builder()
-            .method("GET")
-            .uri(self.config.ws_url.as_ref())
-            .header("Host", host)
-            .header("Connection", "Upgrade")
-            .header("Upgrade", "websocket")
-            .header("Sec-WebSocket-Version", "13")
-            .header(
-                "Sec-WebSocket-Key",
-                tokio_tungstenite::tungstenite::handshake::client::generate_key(),
-            )
-            .header("Authorization", format!("Bearer {}", self.config.ws_token))
-            .header("Device-Id", &device_id)
-            .header("Client-Id", &self.config.client_id)
-            .header("Protocol-Version", "1")
-            .body(())?;
-
-        log::info!("Connecting to {}...", self.config.ws_url);
-        log::debug!("Headers: {:?}", request.headers()); // Debug headers
-        let (ws_stream, _) = connect_async(request).await?;
-        log::info!("Connected!");
-
-        let (mut write, mut read) = ws_stream.split();
-
-        self.tx.send(NetEvent::Connected).await?;
-
-        // 发送Hello消息进行初始化链接
-        // 根据配置动态决定是否在 hello 中声明 MCP 能力
-        let features = if self.config.mcp.enabled {
-            Some(Features { mcp: Some(true) })
+        if use_mqtt {
+            Box::new(MqttTransport::new(self.config.clone(), self.mcp_server.clone()))
         } else {
-            None
-        };
-        let hello_msg = HelloMessage {
-            msg_type: "hello".to_string(),
-            version: 1,
-            transport: "websocket".to_string(),
-            features,
-            audio_params: AudioParams {
-                format: self.config.hello_format.to_string(),
-                sample_rate: self.config.hello_sample_rate,
-                channels: self.config.hello_channels,
-                frame_duration: self.config.hello_frame_duration,
-            },
-        };
-        let hello_json = serde_json::to_string(&hello_msg)?;
-
-        log::info!("Sending Hello: {}", hello_json);
-        write.send(Message::Text(hello_json.into())).await?;
-
-        // 主循环，处理读取和写入
-        loop {
-            tokio::select! {
-                msg = read.next() => {
-                    match msg {
-                        Some(Ok(msg)) => {
-                            match msg {
-                                Message::Text(text) => {
-                                    // 尝试解析消息，检查是否为 MCP 信封格式
-                                    // 服务端下发的 MCP 消息格式: {"type":"mcp","payload":{JSON-RPC},"session_id":"..."}
-                                    let handled = if let Ok(envelope) = serde_json::from_str::<Value>(&text) {
-                                        if envelope.get("type").and_then(|t| t.as_str()) == Some("mcp") {
-                                            if let Some(payload) = envelope.get("payload") {
-                                                let payload_str = payload.to_string();
-                                                log::info!("MCP Request: {}", payload_str);
-                                                if let Some(mcp_response) = self.mcp_server.handle_message(&payload_str).await {
-                                                    if mcp_response.is_empty() {
-                                                        // 通知消息，无需回复
-                                                        true
-                                                    } else {
-                                                        // 将 MCP 响应包装回信封格式发送
-                                                        let session_id = envelope.get("session_id")
-                                                            .and_then(|s| s.as_str())
-                                                            .unwrap_or("");
-                                                        let response_envelope = json!({
-                                                            "type": "mcp",
-                                                            "session_id": session_id,
-                                                            "payload": serde_json::from_str::<Value>(&mcp_response).unwrap_or(Value::Null)
-                                                        });
-                                                        let response_text = serde_json::to_string(&response_envelope).unwrap();
-                                                        log::info!("MCP Response: {}", response_text);
-                                                        write.send(Message::Text(response_text.into())).await?;
-                                                        true
-                                                    }
-                                                } else {
-                                                    false
-                                                }
-                                            } else {
-                                                false
-                                            }
-                                        } else {
-                                            false
-                                        }
-                                    } else {
-                                        false
-                                    };
-
-                                    if !handled {
-                                        // 正常信令通道处理
-                                        log::info!("Received Text: {}", text);
-                                        self.tx.send(NetEvent::Text(text.to_string())).await?;
-                                    }
-                                }
-                                Message::Binary(data) => {
-                                    self.tx.send(NetEvent::Binary(data.to_vec())).await?;
-                                }
-                                Message::Close(frame) => {
-                                    log::info!("Server closed connection: {:?}", frame);
-                                    return Err(anyhow::anyhow!("Connection closed"));
-                                }
-                                _ => {}
-                            }
-                        }
-                        Some(Err(e)) => return Err(e.into()),
-                        None => return Err(anyhow::anyhow!("Connection closed")),
-                    }
-                }
-                Some(cmd) = self.rx_cmd.recv() => {
-                    match cmd {
-                        NetCommand::SendText(text) => {
-                            write.send(Message::Text(text.into())).await?;
-                        }
-                        NetCommand::SendBinary(data) => {
-                            write.send(Message::Binary(data.into())).await?;
-                        }
-                    }
-                }
-                else => break,
-            }
+            Box::new(WebSocketTransport::new(self.config.clone(), self.mcp_server.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(min: u64, max: u64, stable: u64) -> Backoff {
+        Backoff {
+            min: Duration::from_secs(min),
+            max: Duration::from_secs(max),
+            stable: Duration::from_secs(stable),
+            base: Duration::from_secs(min),
+            attempt: 0,
+        }
+    }
+
+    #[test]
+    fn jitter_stays_between_half_base_and_base() {
+        let mut backoff = backoff(2, 60, 30);
+        for base in [2u64, 4, 8, 16, 32, 60, 60, 60] {
+            let delay = backoff.next_delay(None);
+            let low = Duration::from_millis(base * 1000 / 2).max(backoff.min);
+            assert!(delay >= low && delay <= Duration::from_secs(base), "{delay:?} for {base}s");
+        }
+        assert_eq!(backoff.attempt, 8);
+    }
+
+    #[test]
+    fn delay_never_drops_below_min() {
+        let mut backoff = backoff(5, 5, 30);
+        for _ in 0..100 {
+            assert_eq!(backoff.next_delay(None), Duration::from_secs(5));
         }
-        Ok(())
+    }
+
+    #[test]
+    fn resets_after_stable_uptime() {
+        let mut backoff = backoff(1, 60, 30);
+        for _ in 0..6 {
+            backoff.next_delay(Some(Duration::from_secs(3)));
+        }
+        assert_eq!(backoff.base, Duration::from_secs(60));
+
+        // 连接时长不足 stable 时继续沿用当前退避
+        let delay = backoff.next_delay(Some(Duration::from_secs(29)));
+        assert!(delay >= Duration::from_secs(30));
+
+        let delay = backoff.next_delay(Some(Duration::from_secs(30)));
+        assert!(delay <= Duration::from_secs(1));
+        assert_eq!(backoff.attempt, 1);
+        assert_eq!(backoff.base, Duration::from_secs(2));
     }
 }
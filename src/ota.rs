@@ -0,0 +1,509 @@
+//! ota - 应用程序在线升级
+//!
+//! OTA 响应中的固件版本比当前版本新时，下载新的可执行文件并校验 SHA-256，
+//! 然后原子替换当前程序，旧版本保留为 <程序名>.bak 供回滚。
+//! 替换完成后以 EXIT_CODE_UPGRADED 退出，由外部守护进程（如 systemd）重启。
+//!
+//! 新版本首次启动时处于待验证状态（<程序名>.pending），与服务器完成 hello 握手后才确认；
+//! 连续多次启动都未能确认时恢复 .bak 中的旧版本，并在下一次 OTA 请求中上报回滚
+
+use crate::activation::FirmwareInfo;
+use anyhow::Context;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs::{self, File, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// 升级完成后的退出码，守护进程据此区分正常升级重启和异常退出
+pub const EXIT_CODE_UPGRADED: i32 = 75;
+
+/// 有新版本时下载并替换当前程序，返回是否已完成替换
+pub async fn upgrade_if_newer(client: &Client, firmware: &FirmwareInfo) -> anyhow::Result<bool> {
+    let exe = std::env::current_exe().context("Failed to locate current executable")?;
+    upgrade(client, firmware, env!("APP_VERSION"), &exe).await
+}
+
+// 把 exe 从 current 版本升级到 firmware 描述的版本
+async fn upgrade(
+    client: &Client,
+    firmware: &FirmwareInfo,
+    current: &str,
+    exe: &Path,
+) -> anyhow::Result<bool> {
+    if firmware.url.is_empty() || !is_newer(&firmware.version, current) {
+        return Ok(false);
+    }
+    if failed_versions(exe)
+        .iter()
+        .any(|failed| same_version(failed, &firmware.version))
+    {
+        log::warn!(
+            "Firmware {} was rolled back before, skipping upgrade",
+            firmware.version
+        );
+        return Ok(false);
+    }
+    let Some(expected_sha256) = firmware.sha256.as_deref() else {
+        log::warn!(
+            "Firmware {} has no sha256, skipping upgrade",
+            firmware.version
+        );
+        return Ok(false);
+    };
+
+    log::info!(
+        "Upgrading {} -> {} from {}",
+        current,
+        firmware.version,
+        firmware.url
+    );
+
+    // 临时文件与程序放在同一目录，保证 rename 是同一文件系统内的原子操作
+    let download = sibling(exe, "download");
+    if let Err(e) = download_verified(client, &firmware.url, &download, expected_sha256).await {
+        let _ = fs::remove_file(&download);
+        return Err(e);
+    }
+    install(exe, &download, &firmware.version)?;
+    log::info!(
+        "Upgraded to {}, previous version kept at {}",
+        firmware.version,
+        sibling(exe, "bak").display()
+    );
+    Ok(true)
+}
+
+/// 按点分隔的数字逐段比较版本号，无法解析时只要版本不同就视为更新
+/// 末尾的 0 段不参与比较，1.2 与 1.2.0 视为同一版本
+fn is_newer(remote: &str, current: &str) -> bool {
+    let parse = |version: &str| {
+        let mut parts = normalize_version(version)
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()?;
+        while parts.last() == Some(&0) {
+            parts.pop();
+        }
+        Ok::<_, std::num::ParseIntError>(parts)
+    };
+    match (parse(remote), parse(current)) {
+        (Ok(remote), Ok(current)) => remote > current,
+        _ => normalize_version(remote) != normalize_version(current),
+    }
+}
+
+// 服务器下发的版本号可能带 v 前缀
+fn normalize_version(version: &str) -> &str {
+    version.trim().trim_start_matches('v')
+}
+
+/// 按 is_newer 的规则判断两个版本号是否为同一版本
+fn same_version(a: &str, b: &str) -> bool {
+    !is_newer(a, b) && !is_newer(b, a)
+}
+
+// 边下载边计算 SHA-256，校验通过并落盘后才返回
+async fn download_verified(
+    client: &Client,
+    url: &str,
+    path: &Path,
+    expected_sha256: &str,
+) -> anyhow::Result<()> {
+    let mut resp = client.get(url).send().await?.error_for_status()?;
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut size = 0usize;
+    while let Some(chunk) = resp.chunk().await? {
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        size += chunk.len();
+    }
+
+    let actual = hex::encode(hasher.finalize());
+    if !actual.eq_ignore_ascii_case(expected_sha256.trim()) {
+        anyhow::bail!(
+            "Firmware SHA-256 mismatch: expected {}, got {}",
+            expected_sha256,
+            actual
+        );
+    }
+
+    file.set_permissions(Permissions::from_mode(0o755)).await?;
+    file.sync_all().await?;
+    log::info!("Firmware downloaded and verified ({} bytes)", size);
+    Ok(())
+}
+
+// 旧程序先硬链接为 .bak，再把新程序 rename 到原路径，任何时刻原路径上都是一个完整的程序
+fn install(exe: &Path, new: &Path, version: &str) -> anyhow::Result<()> {
+    let backup = sibling(exe, "bak");
+    match fs::remove_file(&backup) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context(format!("Failed to remove {}", backup.display())),
+    }
+    fs::hard_link(exe, &backup)
+        .or_else(|_| fs::copy(exe, &backup).map(|_| ()))
+        .with_context(|| format!("Failed to back up {}", exe.display()))?;
+    // 待验证标记先于替换写入；若替换前掉电，标记中的版本与实际运行的版本不符，启动时会被清除
+    write_json(
+        &sibling(exe, "pending"),
+        &PendingBoot {
+            version: version.to_string(),
+            boot_attempts: 0,
+        },
+    )?;
+    fs::rename(new, exe).with_context(|| format!("Failed to replace {}", exe.display()))?;
+
+    // 目录项的修改也要落盘，防止掉电后 rename 丢失
+    if let Some(dir) = exe.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// 新版本的待验证状态
+#[derive(Serialize, Deserialize)]
+struct PendingBoot {
+    version: String,
+    boot_attempts: u32,
+}
+
+/// 回滚记录，在下一次 OTA 请求中上报后删除
+#[derive(Serialize, Deserialize)]
+struct RollbackReport {
+    failed_version: String,
+    boot_attempts: u32,
+}
+
+/// 启动时检查新版本的待验证状态，返回是否已回滚到旧版本（需要退出等待重启）
+/// 连续 ota_max_boot_attempts 次启动都未确认时恢复 .bak
+/// 在加载配置文件之前调用，最大启动次数只取编译时的值（build.rs 保证不为 0）
+pub fn check_pending_boot() -> anyhow::Result<bool> {
+    let max_boot_attempts = env!("OTA_MAX_BOOT_ATTEMPTS")
+        .parse()
+        .context("Failed to parse OTA_MAX_BOOT_ATTEMPTS")?;
+    let exe = std::env::current_exe().context("Failed to locate current executable")?;
+    check_pending_boot_of(&exe, env!("APP_VERSION"), max_boot_attempts)
+}
+
+fn check_pending_boot_of(exe: &Path, current: &str, max_boot_attempts: u32) -> anyhow::Result<bool> {
+    let marker = sibling(exe, "pending");
+    if !marker.exists() {
+        return Ok(false);
+    }
+    let mut pending: PendingBoot = read_json(&marker)?;
+    // 标记中是服务器下发的版本号，与编译时的 APP_VERSION 写法可能不同（如 v1.2 与 1.2.0）
+    if !same_version(&pending.version, current) {
+        // 标记不属于当前版本（替换未完成或已手动恢复旧版本）
+        fs::remove_file(&marker)?;
+        return Ok(false);
+    }
+
+    if pending.boot_attempts >= max_boot_attempts {
+        let backup = sibling(exe, "bak");
+        if !backup.exists() {
+            log::error!(
+                "Version {} failed {} boots but {} is missing, cannot roll back",
+                pending.version,
+                pending.boot_attempts,
+                backup.display()
+            );
+            fs::remove_file(&marker)?;
+            return Ok(false);
+        }
+        log::error!(
+            "Version {} failed {} boots, rolling back to {}",
+            pending.version,
+            pending.boot_attempts,
+            backup.display()
+        );
+        // 回滚记录上报后会删除，失败版本另外保存，避免服务器仍下发该版本时反复升级、回滚
+        let mut failed = failed_versions(exe);
+        if !failed.iter().any(|version| same_version(version, &pending.version)) {
+            failed.push(pending.version.clone());
+            write_json(&sibling(exe, "failed"), &failed)?;
+        }
+        write_json(
+            &sibling(exe, "rollback"),
+            &RollbackReport {
+                failed_version: pending.version,
+                boot_attempts: pending.boot_attempts,
+            },
+        )?;
+        fs::rename(&backup, exe)
+            .with_context(|| format!("Failed to restore {}", backup.display()))?;
+        fs::remove_file(&marker)?;
+        if let Some(dir) = exe.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        return Ok(true);
+    }
+
+    pending.boot_attempts += 1;
+    log::info!(
+        "Version {} pending verification (boot attempt {}/{})",
+        pending.version,
+        pending.boot_attempts,
+        max_boot_attempts
+    );
+    write_json(&marker, &pending)?;
+    Ok(false)
+}
+
+/// 与服务器完成握手后调用，确认新版本可用
+pub fn confirm_boot() {
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    let marker = sibling(&exe, "pending");
+    if marker.exists() {
+        match fs::remove_file(&marker) {
+            Ok(()) => log::info!("Version {} verified", env!("APP_VERSION")),
+            Err(e) => log::error!("Failed to remove {}: {}", marker.display(), e),
+        }
+    }
+}
+
+/// OTA 请求体中的 ota 字段，有未上报的回滚记录时附带 rollback
+pub fn ota_report() -> Value {
+    let report = std::env::current_exe()
+        .ok()
+        .map(|exe| sibling(&exe, "rollback"))
+        .filter(|path| path.exists())
+        .and_then(|path| read_json::<RollbackReport>(&path).ok());
+    match report {
+        Some(report) => json!({ "rollback": report }),
+        None => json!({}),
+    }
+}
+
+/// OTA 请求成功后调用，删除已上报的回滚记录
+pub fn clear_rollback_report() {
+    if let Ok(exe) = std::env::current_exe() {
+        let path = sibling(&exe, "rollback");
+        if path.exists() && fs::remove_file(&path).is_ok() {
+            log::info!("Rollback reported to server");
+        }
+    }
+}
+
+// 回滚过的版本列表，文件不存在或无法解析时视为空
+fn failed_versions(exe: &Path) -> Vec<String> {
+    let path = sibling(exe, "failed");
+    if !path.exists() {
+        return Vec::new();
+    }
+    read_json(&path).unwrap_or_else(|e| {
+        log::warn!("{:#}", e);
+        Vec::new()
+    })
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+// 写入后 fsync，标记文件需要在掉电后依然可靠
+fn write_json<T: Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    let mut file =
+        File::create(path).with_context(|| format!("Failed to write {}", path.display()))?;
+    serde_json::to_writer(&mut file, value)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+// 与程序同目录、追加扩展名的路径，如 xiaozhi.bak
+fn sibling(exe: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(exe.as_os_str());
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+
+    const NEW_FIRMWARE: &[u8] = b"new firmware";
+
+    // 每个测试使用独立的临时目录，里面放一个内容为 "old" 的假程序
+    struct TempExe {
+        dir: PathBuf,
+        exe: PathBuf,
+    }
+
+    impl TempExe {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("ota-test-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            let exe = dir.join("app");
+            fs::write(&exe, "old").unwrap();
+            Self { dir, exe }
+        }
+    }
+
+    impl Drop for TempExe {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn firmware(server: &TestServer, sha256: &str) -> FirmwareInfo {
+        FirmwareInfo {
+            version: "v1.1.0".to_string(),
+            url: format!("{}/firmware/app", server.url),
+            sha256: Some(sha256.to_string()),
+        }
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert!(is_newer("1.2.1", "1.2.0"));
+        assert!(is_newer("1.10.0", "1.9.9"));
+        assert!(!is_newer("1.2.0", "1.2.1"));
+        assert!(!is_newer("1.2.0", "1.2.0"));
+    }
+
+    #[test]
+    fn ignores_v_prefix() {
+        assert!(is_newer("v1.3", "1.2.9"));
+        assert!(is_newer("1.3", "v1.2.9"));
+        assert!(!is_newer("v1.2.0", "1.2.0"));
+    }
+
+    #[test]
+    fn compares_differing_segment_counts() {
+        assert!(is_newer("1.2.1", "1.2"));
+        assert!(is_newer("2", "1.9.9"));
+        assert!(!is_newer("1.2", "1.2.1"));
+        assert!(!is_newer("1.2.0", "1.2"));
+        assert!(!is_newer("1.2", "1.2.0"));
+    }
+
+    #[test]
+    fn non_numeric_versions_upgrade_when_different() {
+        assert!(is_newer("1.2.0-beta", "1.2.0"));
+        assert!(is_newer("nightly", "1.2.0"));
+        assert!(!is_newer("nightly", "nightly"));
+    }
+
+    #[tokio::test]
+    async fn skips_same_version_or_missing_sha() {
+        let temp = TempExe::new();
+        let server = TestServer::start(Vec::new()).await;
+        let client = Client::new();
+
+        let same = firmware(&server, "00");
+        assert!(!upgrade(&client, &same, "1.1.0", &temp.exe).await.unwrap());
+        let unsigned = FirmwareInfo { sha256: None, ..firmware(&server, "") };
+        assert!(!upgrade(&client, &unsigned, "1.0.0", &temp.exe).await.unwrap());
+
+        assert!(server.paths().is_empty());
+        assert_eq!(fs::read(&temp.exe).unwrap(), b"old");
+    }
+
+    #[tokio::test]
+    async fn sha_mismatch_leaves_exe_untouched() {
+        let temp = TempExe::new();
+        let server = TestServer::start(vec![(200, NEW_FIRMWARE.to_vec())]).await;
+        let firmware = firmware(&server, &hex::encode(Sha256::digest(b"other")));
+
+        let err = upgrade(&Client::new(), &firmware, "1.0.0", &temp.exe).await.unwrap_err();
+        assert!(err.to_string().contains("SHA-256 mismatch"), "{err}");
+
+        assert_eq!(server.paths(), ["/firmware/app"]);
+        assert!(!sibling(&temp.exe, "download").exists());
+        assert!(!sibling(&temp.exe, "bak").exists());
+        assert!(!sibling(&temp.exe, "pending").exists());
+        assert_eq!(fs::read(&temp.exe).unwrap(), b"old");
+    }
+
+    #[tokio::test]
+    async fn installs_verified_firmware() {
+        let temp = TempExe::new();
+        let server = TestServer::start(vec![(200, NEW_FIRMWARE.to_vec())]).await;
+        // 服务器可能下发大写的 hex
+        let sha256 = hex::encode_upper(Sha256::digest(NEW_FIRMWARE));
+
+        let firmware = firmware(&server, &sha256);
+        assert!(upgrade(&Client::new(), &firmware, "1.0.0", &temp.exe).await.unwrap());
+
+        assert_eq!(fs::read(&temp.exe).unwrap(), NEW_FIRMWARE);
+        assert_eq!(fs::metadata(&temp.exe).unwrap().permissions().mode() & 0o777, 0o755);
+        assert_eq!(fs::read(sibling(&temp.exe, "bak")).unwrap(), b"old");
+        assert!(!sibling(&temp.exe, "download").exists());
+        let pending: PendingBoot = read_json(&sibling(&temp.exe, "pending")).unwrap();
+        assert_eq!((pending.version.as_str(), pending.boot_attempts), ("v1.1.0", 0));
+    }
+
+    fn write_pending(exe: &Path, version: &str, boot_attempts: u32) {
+        let pending = PendingBoot { version: version.to_string(), boot_attempts };
+        write_json(&sibling(exe, "pending"), &pending).unwrap();
+    }
+
+    #[test]
+    fn pending_marker_matches_normalized_version() {
+        let temp = TempExe::new();
+        write_pending(&temp.exe, "v1.1", 0);
+
+        assert!(!check_pending_boot_of(&temp.exe, "1.1.0", 3).unwrap());
+        let pending: PendingBoot = read_json(&sibling(&temp.exe, "pending")).unwrap();
+        assert_eq!(pending.boot_attempts, 1);
+    }
+
+    #[test]
+    fn pending_marker_of_other_version_is_removed() {
+        let temp = TempExe::new();
+        write_pending(&temp.exe, "v1.1.0", 0);
+
+        assert!(!check_pending_boot_of(&temp.exe, "1.0.0", 3).unwrap());
+        assert!(!sibling(&temp.exe, "pending").exists());
+    }
+
+    #[test]
+    fn rolls_back_after_max_boot_attempts() {
+        let temp = TempExe::new();
+        fs::write(sibling(&temp.exe, "bak"), "previous").unwrap();
+        write_pending(&temp.exe, "v1.1.0", 3);
+
+        assert!(check_pending_boot_of(&temp.exe, "1.1.0", 3).unwrap());
+        assert_eq!(fs::read(&temp.exe).unwrap(), b"previous");
+        assert!(!sibling(&temp.exe, "pending").exists());
+        let report: RollbackReport = read_json(&sibling(&temp.exe, "rollback")).unwrap();
+        assert_eq!((report.failed_version.as_str(), report.boot_attempts), ("v1.1.0", 3));
+        let failed: Vec<String> = read_json(&sibling(&temp.exe, "failed")).unwrap();
+        assert_eq!(failed, ["v1.1.0"]);
+    }
+
+    #[tokio::test]
+    async fn skips_rolled_back_version_after_report() {
+        let temp = TempExe::new();
+        fs::write(sibling(&temp.exe, "bak"), "previous").unwrap();
+        write_pending(&temp.exe, "v1.1.0", 3);
+        assert!(check_pending_boot_of(&temp.exe, "1.1.0", 3).unwrap());
+        // 模拟回滚记录已上报
+        fs::remove_file(sibling(&temp.exe, "rollback")).unwrap();
+
+        let server = TestServer::start(vec![(200, NEW_FIRMWARE.to_vec())]).await;
+        let sha256 = hex::encode(Sha256::digest(NEW_FIRMWARE));
+        let same = FirmwareInfo { version: "1.1".to_string(), ..firmware(&server, &sha256) };
+        assert!(!upgrade(&Client::new(), &same, "1.0.0", &temp.exe).await.unwrap());
+        assert!(server.paths().is_empty());
+        assert_eq!(fs::read(&temp.exe).unwrap(), b"previous");
+
+        let newer = FirmwareInfo { version: "1.1.1".to_string(), ..firmware(&server, &sha256) };
+        assert!(upgrade(&Client::new(), &newer, "1.0.0", &temp.exe).await.unwrap());
+        assert_eq!(fs::read(&temp.exe).unwrap(), NEW_FIRMWARE);
+    }
+}
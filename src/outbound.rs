@@ -0,0 +1,281 @@
+//! outbound - 出站连接的代理与 TLS 设置
+//!
+//! WebSocket、MQTT、激活请求和 MCP HTTP 工具都从这里获取 TLS 配置、HTTP 客户端和 TCP 连接，
+//! 保证 [outbound] 中的代理、私有 CA 和客户端证书对所有连接一致生效
+
+use crate::clock;
+use crate::config::OutboundConfig;
+use anyhow::Context;
+use base64::Engine;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+use url::Url;
+
+const DEFAULT_SOCKS5_PORT: u16 = 1080;
+// 代理 CONNECT 响应头的长度上限
+const MAX_CONNECT_RESPONSE: usize = 8192;
+
+/// 构造 TLS 客户端配置：内置根证书 + ca_file 中的私有 CA，配置了客户端证书时启用双向 TLS
+/// 严格校验证书有效期，用于时钟校准之后建立的 WebSocket / MQTT 连接
+pub fn tls_client_config(outbound: &OutboundConfig) -> anyhow::Result<Arc<rustls::ClientConfig>> {
+    build_tls_config(outbound, Duration::ZERO)
+}
+
+fn build_tls_config(
+    outbound: &OutboundConfig,
+    not_yet_valid_window: Duration,
+) -> anyhow::Result<Arc<rustls::ClientConfig>> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if !outbound.ca_file.is_empty() {
+        for cert in CertificateDer::pem_file_iter(&outbound.ca_file)
+            .with_context(|| format!("Failed to read CA file {}", outbound.ca_file))?
+        {
+            let cert = cert.with_context(|| format!("Invalid CA file {}", outbound.ca_file))?;
+            roots.add(cert)?;
+        }
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = ClockAwareVerifier {
+        inner: WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()?,
+        not_yet_valid_window,
+    };
+    let builder = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+    let config = if outbound.client_cert_file.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        let certs = CertificateDer::pem_file_iter(&outbound.client_cert_file)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| {
+                format!("Failed to read client certificate {}", outbound.client_cert_file)
+            })?;
+        let key = PrivateKeyDer::from_pem_file(&outbound.client_key_file)
+            .with_context(|| format!("Failed to read client key {}", outbound.client_key_file))?;
+        builder.with_client_auth_cert(certs, key)?
+    };
+    Ok(Arc::new(config))
+}
+
+/// 证书校验使用应用层时钟（见 clock 模块），而不是可能停在 1970 年的系统时间；
+/// 时钟尚未用服务器时间校准时，容忍生效时间在 not_yet_valid_window 内的证书
+#[derive(Debug)]
+struct ClockAwareVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    not_yet_valid_window: Duration,
+}
+
+impl ServerCertVerifier for ClockAwareVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let now = clock::unix_now();
+        let verify = |now: Duration| {
+            self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                UnixTime::since_unix_epoch(now),
+            )
+        };
+        match verify(now) {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. },
+            )) if !clock::is_synced() && !self.not_yet_valid_window.is_zero() => {
+                let verified = verify(now + self.not_yet_valid_window)?;
+                log::warn!(
+                    "Accepting not-yet-valid certificate for {} because the clock is not synced yet",
+                    server_name.to_str()
+                );
+                Ok(verified)
+            }
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// 构造使用代理和自定义证书的 HTTP 客户端，严格校验证书有效期，用于 MCP HTTP 工具
+pub fn http_client(outbound: &OutboundConfig) -> anyhow::Result<reqwest::Client> {
+    build_http_client(outbound, tls_client_config(outbound)?)
+}
+
+/// OTA / 激活请求使用的 HTTP 客户端
+/// 这是时钟校准前唯一的 TLS 连接，校准前容忍生效时间在 not_yet_valid_window_secs 内的证书
+pub fn ota_http_client(outbound: &OutboundConfig) -> anyhow::Result<reqwest::Client> {
+    let window = Duration::from_secs(outbound.not_yet_valid_window_secs);
+    build_http_client(outbound, build_tls_config(outbound, window)?)
+}
+
+fn build_http_client(
+    outbound: &OutboundConfig,
+    tls: Arc<rustls::ClientConfig>,
+) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().use_preconfigured_tls((*tls).clone());
+    if let Some(proxy) = proxy_url(outbound)? {
+        // 局域网内的 MCP HTTP 工具等可通过 NO_PROXY 环境变量绕过代理
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?.no_proxy(reqwest::NoProxy::from_env()));
+    }
+    Ok(builder.build()?)
+}
+
+/// 解析代理地址，未配置代理时返回 None
+pub fn proxy_url(outbound: &OutboundConfig) -> anyhow::Result<Option<Url>> {
+    let proxy = outbound.proxy.trim();
+    if proxy.is_empty() {
+        return Ok(None);
+    }
+    let url = Url::parse(proxy).with_context(|| format!("Invalid proxy URL {}", proxy))?;
+    if url.host_str().is_none() {
+        anyhow::bail!("Proxy URL {} missing host", proxy);
+    }
+    Ok(Some(url))
+}
+
+/// 建立到 host:port 的 TCP 连接，配置了代理时经代理隧道转发
+pub async fn connect_tcp(outbound: &OutboundConfig, host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    let Some(proxy) = proxy_url(outbound)? else {
+        return Ok(TcpStream::connect((host, port)).await?);
+    };
+    // proxy_url 已检查过 host
+    let proxy_host = proxy.host_str().unwrap_or_default();
+
+    log::info!("Connecting to {}:{} via {} proxy {}", host, port, proxy.scheme(), proxy_host);
+    match proxy.scheme() {
+        "http" => {
+            let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+            let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+            http_connect(&mut stream, &proxy, host, port).await?;
+            Ok(stream)
+        }
+        "socks5" => {
+            let proxy_addr = (proxy_host, proxy.port().unwrap_or(DEFAULT_SOCKS5_PORT));
+            let stream = if proxy.username().is_empty() {
+                Socks5Stream::connect(proxy_addr, (host, port)).await?
+            } else {
+                Socks5Stream::connect_with_password(
+                    proxy_addr,
+                    (host, port),
+                    proxy.username(),
+                    proxy.password().unwrap_or_default(),
+                )
+                .await?
+            };
+            Ok(stream.into_inner())
+        }
+        scheme => anyhow::bail!("Unsupported proxy scheme: {}", scheme),
+    }
+}
+
+// 通过 HTTP CONNECT 建立隧道
+async fn http_connect(stream: &mut TcpStream, proxy: &Url, host: &str, port: u16) -> anyhow::Result<()> {
+    // IPv6 地址需要加方括号，否则无法与端口区分
+    let authority = if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    };
+    let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if !proxy.username().is_empty() {
+        let credentials = format!("{}:{}", proxy.username(), proxy.password().unwrap_or_default());
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", encoded));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // 逐字节读取响应头，避免把隧道中的后续数据读走
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE {
+            anyhow::bail!("Proxy CONNECT response too long");
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => Ok(()),
+        _ => anyhow::bail!("Proxy CONNECT failed: {}", status_line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn connect_request(host: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(stream.read_u8().await.unwrap());
+            }
+            stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let mut stream = TcpStream::connect(proxy.socket_addrs(|| None).unwrap()[0]).await.unwrap();
+        http_connect(&mut stream, &proxy, host, 443).await.unwrap();
+        server.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn http_connect_uses_host_and_port() {
+        let request = connect_request("example.com").await;
+        assert!(request.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+        assert!(request.contains("\r\nHost: example.com:443\r\n"));
+    }
+
+    #[tokio::test]
+    async fn http_connect_brackets_ipv6_host() {
+        for host in ["::1", "[::1]"] {
+            let request = connect_request(host).await;
+            assert!(request.starts_with("CONNECT [::1]:443 HTTP/1.1\r\n"), "{request}");
+            assert!(request.contains("\r\nHost: [::1]:443\r\n"));
+        }
+    }
+}
@@ -1,11 +1,379 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
 
+// 字段缺失或为 null 时取默认值，避免个别字段不规范导致整条消息被丢弃
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// 服务器下发的消息，按 "type" 字段区分
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerMessage {
+    Hello(ServerHello),
+    Stt(SttMessage),
+    Tts(TtsMessage),
+    Llm(LlmMessage),
+    Iot(IotMessage),
+    Mcp(McpMessage),
+    System(SystemMessage),
+    Alert(AlertMessage),
+    Goodbye(GoodbyeMessage),
+    // 未知类型，保留原始文本日志即可
+    #[serde(other)]
+    Unknown,
+}
+
+impl ServerMessage {
+    pub fn session_id(&self) -> Option<&str> {
+        match self {
+            Self::Hello(m) => m.session_id.as_deref(),
+            Self::Stt(m) => m.session_id.as_deref(),
+            Self::Tts(m) => m.session_id.as_deref(),
+            Self::Llm(m) => m.session_id.as_deref(),
+            Self::Iot(m) => m.session_id.as_deref(),
+            Self::Mcp(m) => m.session_id.as_deref(),
+            Self::System(m) => m.session_id.as_deref(),
+            Self::Alert(m) => m.session_id.as_deref(),
+            Self::Goodbye(m) => m.session_id.as_deref(),
+            Self::Unknown => None,
+        }
+    }
+}
+
+// 服务器 hello 中的音频参数（服务器下发 TTS 音频的格式）
+#[derive(Deserialize, Debug, Clone)]
+pub struct AudioParams {
+    // 缺省时按本地配置的 stream_format 处理
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    #[serde(default)]
+    pub channels: Option<u8>,
+    #[serde(default)]
+    pub frame_duration: Option<u32>,
+}
+
+// MQTT 传输下服务器 hello 中的 UDP 音频通道参数，key/nonce 为十六进制字符串
+#[derive(Deserialize, Debug, Clone)]
+pub struct UdpParams {
+    pub server: String,
+    pub port: u16,
+    pub key: String,
+    pub nonce: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ServerHello {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub transport: Option<String>,
+    #[serde(default)]
+    pub version: Option<u8>,
+    #[serde(default)]
+    pub audio_params: Option<AudioParams>,
+    #[serde(default)]
+    pub udp: Option<UdpParams>,
+}
+
+// 语音识别结果
 #[derive(Deserialize, Debug, Clone)]
-pub struct ServerMessage {
-    #[serde(rename = "type")]
-    pub msg_type: String,
-    pub command: Option<String>,
+pub struct SttMessage {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub text: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TtsState {
+    Start,
+    Stop,
+    SentenceStart,
+    SentenceEnd,
+    #[serde(other)]
+    #[default]
+    Unknown,
+}
+
+impl TtsState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+            Self::SentenceStart => "sentence_start",
+            Self::SentenceEnd => "sentence_end",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TtsMessage {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub state: TtsState,
+    #[serde(default)]
     pub text: Option<String>,
-    pub state: Option<String>,
+}
+
+// 服务器表情集合，未知表情按 neutral 处理
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Emotion {
+    Happy,
+    Laughing,
+    Funny,
+    Sad,
+    Angry,
+    Crying,
+    Loving,
+    Embarrassed,
+    Surprised,
+    Shocked,
+    Thinking,
+    Winking,
+    Cool,
+    Relaxed,
+    Delicious,
+    Kissy,
+    Confident,
+    Sleepy,
+    Silly,
+    Confused,
+    #[serde(other)]
+    Neutral,
+}
+
+impl Emotion {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Neutral => "neutral",
+            Self::Happy => "happy",
+            Self::Laughing => "laughing",
+            Self::Funny => "funny",
+            Self::Sad => "sad",
+            Self::Angry => "angry",
+            Self::Crying => "crying",
+            Self::Loving => "loving",
+            Self::Embarrassed => "embarrassed",
+            Self::Surprised => "surprised",
+            Self::Shocked => "shocked",
+            Self::Thinking => "thinking",
+            Self::Winking => "winking",
+            Self::Cool => "cool",
+            Self::Relaxed => "relaxed",
+            Self::Delicious => "delicious",
+            Self::Kissy => "kissy",
+            Self::Confident => "confident",
+            Self::Sleepy => "sleepy",
+            Self::Silly => "silly",
+            Self::Confused => "confused",
+        }
+    }
+}
+
+// 大模型回复附带的表情，如 {"type":"llm","emotion":"happy","text":"😀"}
+#[derive(Deserialize, Debug, Clone)]
+pub struct LlmMessage {
+    #[serde(default)]
     pub session_id: Option<String>,
+    #[serde(default)]
+    pub emotion: Option<Emotion>,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+// 旧版 IoT 控制指令
+#[derive(Deserialize, Debug, Clone)]
+pub struct IotMessage {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub commands: Vec<Value>,
+}
+
+// MCP 信封，payload 为 JSON-RPC 消息
+#[derive(Deserialize, Debug, Clone)]
+pub struct McpMessage {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub payload: Option<Value>,
+}
+
+// 系统指令，如 {"type":"system","command":"reboot"}
+#[derive(Deserialize, Debug, Clone)]
+pub struct SystemMessage {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub command: String,
+}
+
+// 服务器告警，如 {"type":"alert","status":"Warning","message":"...","emotion":"sad"}
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlertMessage {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub status: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub message: String,
+    #[serde(default)]
+    pub emotion: Option<Emotion>,
+}
+
+// 服务器结束会话
+#[derive(Deserialize, Debug, Clone)]
+pub struct GoodbyeMessage {
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> ServerMessage {
+        serde_json::from_str(json).unwrap_or_else(|e| panic!("{}: {}", json, e))
+    }
+
+    #[test]
+    fn parses_server_hello() {
+        let msg = parse(
+            r#"{"type":"hello","version":1,"transport":"websocket","session_id":"a1b2",
+                "audio_params":{"format":"opus","sample_rate":24000,"channels":1,"frame_duration":60}}"#,
+        );
+        let ServerMessage::Hello(hello) = msg else {
+            panic!("not a hello: {:?}", msg);
+        };
+        assert_eq!(hello.session_id.as_deref(), Some("a1b2"));
+        let params = hello.audio_params.unwrap();
+        assert_eq!(params.format.as_deref(), Some("opus"));
+        assert_eq!((params.sample_rate, params.channels, params.frame_duration), (Some(24000), Some(1), Some(60)));
+
+        // audio_params 中只有部分字段时仍能解析
+        let msg = parse(r#"{"type":"hello","transport":"udp","audio_params":{"sample_rate":16000}}"#);
+        let ServerMessage::Hello(hello) = msg else {
+            panic!("not a hello: {:?}", msg);
+        };
+        let params = hello.audio_params.unwrap();
+        assert_eq!((params.format, params.sample_rate, params.channels), (None, Some(16000), None));
+        let msg = parse(r#"{"type":"hello","audio_params":{"format":null}}"#);
+        let ServerMessage::Hello(hello) = msg else {
+            panic!("not a hello: {:?}", msg);
+        };
+        assert_eq!(hello.audio_params.unwrap().format, None);
+    }
+
+    #[test]
+    fn parses_iot_with_missing_or_null_commands() {
+        let msg = parse(r#"{"type":"iot","commands":[{"name":"Lamp","method":"TurnOn"}]}"#);
+        let ServerMessage::Iot(iot) = msg else {
+            panic!("not an iot message: {:?}", msg);
+        };
+        assert_eq!(iot.commands.len(), 1);
+        assert_eq!(iot.commands[0]["method"], "TurnOn");
+
+        for json in [r#"{"type":"iot"}"#, r#"{"type":"iot","commands":null}"#] {
+            let ServerMessage::Iot(iot) = parse(json) else {
+                panic!("not an iot message: {}", json);
+            };
+            assert!(iot.commands.is_empty());
+        }
+    }
+
+    #[test]
+    fn parses_stt_with_missing_or_null_text() {
+        for (json, expected) in [
+            (r#"{"type":"stt","text":"今天天气怎么样","session_id":"a1b2"}"#, "今天天气怎么样"),
+            (r#"{"type":"stt","text":null,"session_id":"a1b2"}"#, ""),
+            (r#"{"type":"stt","session_id":"a1b2"}"#, ""),
+        ] {
+            match parse(json) {
+                ServerMessage::Stt(stt) => assert_eq!(stt.text, expected),
+                other => panic!("{}: {:?}", json, other),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_tts_states() {
+        for (json, expected) in [
+            (r#"{"type":"tts","state":"start","session_id":"a1b2"}"#, TtsState::Start),
+            (r#"{"type":"tts","state":"sentence_start","text":"今天是晴天","session_id":"a1b2"}"#, TtsState::SentenceStart),
+            (r#"{"type":"tts","state":"stop","session_id":"a1b2"}"#, TtsState::Stop),
+            (r#"{"type":"tts","state":"paused"}"#, TtsState::Unknown),
+            (r#"{"type":"tts","state":null,"text":"你好"}"#, TtsState::Unknown),
+            (r#"{"type":"tts","text":"你好"}"#, TtsState::Unknown),
+        ] {
+            match parse(json) {
+                ServerMessage::Tts(tts) => assert_eq!(tts.state, expected, "{}", json),
+                other => panic!("{}: {:?}", json, other),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_llm_emotion() {
+        match parse(r#"{"type":"llm","text":"😊","emotion":"happy","session_id":"a1b2"}"#) {
+            ServerMessage::Llm(llm) => assert_eq!(llm.emotion, Some(Emotion::Happy)),
+            other => panic!("{:?}", other),
+        }
+        match parse(r#"{"type":"llm","emotion":"bored"}"#) {
+            ServerMessage::Llm(llm) => assert_eq!(llm.emotion, Some(Emotion::Neutral)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_mcp_payload() {
+        let msg = parse(
+            r#"{"type":"mcp","session_id":"a1b2","payload":{"jsonrpc":"2.0","method":"tools/list","id":2}}"#,
+        );
+        match msg {
+            ServerMessage::Mcp(mcp) => assert_eq!(mcp.payload.unwrap()["method"], "tools/list"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_system_and_alert_with_missing_fields() {
+        match parse(r#"{"type":"system","command":"reboot"}"#) {
+            ServerMessage::System(system) => assert_eq!(system.command, "reboot"),
+            other => panic!("{:?}", other),
+        }
+        match parse(r#"{"type":"system","command":null}"#) {
+            ServerMessage::System(system) => assert!(system.command.is_empty()),
+            other => panic!("{:?}", other),
+        }
+        match parse(r#"{"type":"system"}"#) {
+            ServerMessage::System(system) => assert!(system.command.is_empty()),
+            other => panic!("{:?}", other),
+        }
+        match parse(r#"{"type":"alert","status":"Warning","message":null,"emotion":"sad"}"#) {
+            ServerMessage::Alert(alert) => {
+                assert_eq!(alert.status, "Warning");
+                assert!(alert.message.is_empty());
+                assert_eq!(alert.emotion, Some(Emotion::Sad));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_goodbye_and_unknown_types() {
+        let goodbye = parse(r#"{"type":"goodbye","session_id":"a1b2"}"#);
+        assert_eq!(goodbye.session_id(), Some("a1b2"));
+        assert!(matches!(parse(r#"{"type":"custom","foo":1}"#), ServerMessage::Unknown));
+    }
 }
@@ -0,0 +1,259 @@
+use crate::net_link::AudioFrame;
+
+// WebSocket 二进制帧的协议版本，由 hello 中的 version 与 Protocol-Version 头协商
+// - v1: 帧内容即为 Opus 数据
+// - v2: [version:2][type:2][reserved:4][timestamp:4][payload_size:4] + payload
+// - v3: [type:1][reserved:1][payload_size:2] + payload
+// 多字节字段均为大端
+
+/// 帧类型：0 为音频，1 为 JSON
+const FRAME_TYPE_AUDIO: u16 = 0;
+
+const V2_HEADER_SIZE: usize = 16;
+const V3_HEADER_SIZE: usize = 4;
+
+/// 协议 v2 帧，timestamp 供服务器端回声消除对齐使用
+#[derive(Debug)]
+pub struct BinaryProtocol2 {
+    pub version: u16,
+    pub frame_type: u16,
+    pub reserved: u32,
+    pub timestamp: u32,
+    pub payload: Vec<u8>,
+}
+
+impl BinaryProtocol2 {
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < V2_HEADER_SIZE {
+            anyhow::bail!("v2 frame too short: {} bytes", data.len());
+        }
+        let u32_at = |i: usize| u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let payload_size = u32_at(12) as usize;
+        let payload = data
+            .get(V2_HEADER_SIZE..V2_HEADER_SIZE + payload_size)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "v2 payload_size {} exceeds frame ({} bytes)",
+                    payload_size,
+                    data.len()
+                )
+            })?;
+        Ok(Self {
+            version: u16::from_be_bytes([data[0], data[1]]),
+            frame_type: u16::from_be_bytes([data[2], data[3]]),
+            reserved: u32_at(4),
+            timestamp: u32_at(8),
+            payload: payload.to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(V2_HEADER_SIZE + self.payload.len());
+        data.extend_from_slice(&self.version.to_be_bytes());
+        data.extend_from_slice(&self.frame_type.to_be_bytes());
+        data.extend_from_slice(&self.reserved.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        data.extend_from_slice(&self.payload);
+        data
+    }
+}
+
+/// 协议 v3 帧，去掉了版本号和时间戳的精简帧头
+#[derive(Debug)]
+pub struct BinaryProtocol3 {
+    pub frame_type: u8,
+    pub reserved: u8,
+    pub payload: Vec<u8>,
+}
+
+impl BinaryProtocol3 {
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() < V3_HEADER_SIZE {
+            anyhow::bail!("v3 frame too short: {} bytes", data.len());
+        }
+        let payload_size = u16::from_be_bytes([data[2], data[3]]) as usize;
+        let payload = data
+            .get(V3_HEADER_SIZE..V3_HEADER_SIZE + payload_size)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "v3 payload_size {} exceeds frame ({} bytes)",
+                    payload_size,
+                    data.len()
+                )
+            })?;
+        Ok(Self {
+            frame_type: data[0],
+            reserved: data[1],
+            payload: payload.to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let payload_size = u16::try_from(self.payload.len())
+            .map_err(|_| anyhow::anyhow!("v3 payload too large: {} bytes", self.payload.len()))?;
+        let mut data = Vec::with_capacity(V3_HEADER_SIZE + self.payload.len());
+        data.push(self.frame_type);
+        data.push(self.reserved);
+        data.extend_from_slice(&payload_size.to_be_bytes());
+        data.extend_from_slice(&self.payload);
+        Ok(data)
+    }
+}
+
+/// 按协商的版本封装 / 解析 WebSocket 二进制帧
+pub struct BinaryCodec {
+    version: u8,
+}
+
+impl BinaryCodec {
+    pub fn new(version: u8) -> Self {
+        Self { version }
+    }
+
+    pub fn encode(&self, frame: AudioFrame) -> anyhow::Result<Vec<u8>> {
+        match self.version {
+            2 => Ok(BinaryProtocol2 {
+                version: 2,
+                frame_type: FRAME_TYPE_AUDIO,
+                reserved: 0,
+                timestamp: frame.timestamp,
+                payload: frame.payload,
+            }
+            .encode()),
+            3 => BinaryProtocol3 {
+                frame_type: FRAME_TYPE_AUDIO as u8,
+                reserved: 0,
+                payload: frame.payload,
+            }
+            .encode(),
+            _ => Ok(frame.payload),
+        }
+    }
+
+    /// 解析服务器下发的二进制帧，非音频帧返回 None
+    pub fn decode(&self, data: &[u8]) -> anyhow::Result<Option<AudioFrame>> {
+        let (frame_type, frame) = match self.version {
+            2 => {
+                let packet = BinaryProtocol2::decode(data)?;
+                let frame = AudioFrame {
+                    timestamp: packet.timestamp,
+                    payload: packet.payload,
+                };
+                (packet.frame_type, frame)
+            }
+            3 => {
+                let packet = BinaryProtocol3::decode(data)?;
+                let frame = AudioFrame {
+                    timestamp: 0,
+                    payload: packet.payload,
+                };
+                (packet.frame_type as u16, frame)
+            }
+            _ => {
+                let frame = AudioFrame {
+                    timestamp: 0,
+                    payload: data.to_vec(),
+                };
+                (FRAME_TYPE_AUDIO, frame)
+            }
+        };
+
+        if frame_type != FRAME_TYPE_AUDIO {
+            log::warn!("Unsupported binary frame type {}, dropped", frame_type);
+            return Ok(None);
+        }
+        Ok(Some(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: u32, payload: &[u8]) -> AudioFrame {
+        AudioFrame {
+            timestamp,
+            payload: payload.to_vec(),
+        }
+    }
+
+    #[test]
+    fn v1_is_raw_payload() {
+        let codec = BinaryCodec::new(1);
+        assert_eq!(codec.encode(frame(42, b"opus")).unwrap(), b"opus");
+        let decoded = codec.decode(b"opus").unwrap().unwrap();
+        assert_eq!((decoded.timestamp, decoded.payload.as_slice()), (0, &b"opus"[..]));
+    }
+
+    #[test]
+    fn v2_header_layout() {
+        let data = BinaryCodec::new(2).encode(frame(0x0A0B_0C0D, b"abc")).unwrap();
+        assert_eq!(
+            data,
+            [
+                0, 2, // version
+                0, 0, // type: audio
+                0, 0, 0, 0, // reserved
+                0x0A, 0x0B, 0x0C, 0x0D, // timestamp
+                0, 0, 0, 3, // payload size
+                b'a', b'b', b'c',
+            ]
+        );
+    }
+
+    #[test]
+    fn v2_round_trip_keeps_timestamp() {
+        let codec = BinaryCodec::new(2);
+        let data = codec.encode(frame(123_456, b"payload")).unwrap();
+        let decoded = codec.decode(&data).unwrap().unwrap();
+        assert_eq!(decoded.timestamp, 123_456);
+        assert_eq!(decoded.payload, b"payload");
+    }
+
+    #[test]
+    fn v3_header_layout_and_round_trip() {
+        let codec = BinaryCodec::new(3);
+        let data = codec.encode(frame(99, b"xy")).unwrap();
+        assert_eq!(data, [0, 0, 0, 2, b'x', b'y']);
+        let decoded = codec.decode(&data).unwrap().unwrap();
+        assert_eq!((decoded.timestamp, decoded.payload.as_slice()), (0, &b"xy"[..]));
+    }
+
+    #[test]
+    fn v3_rejects_oversized_payload() {
+        let payload = vec![0u8; u16::MAX as usize + 1];
+        assert!(BinaryCodec::new(3).encode(frame(0, &payload)).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        assert!(BinaryCodec::new(2).decode(&[0; 15]).is_err());
+        // payload_size claims 10 bytes but only 2 follow
+        let mut short = vec![0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10];
+        short.extend_from_slice(&[1, 2]);
+        assert!(BinaryCodec::new(2).decode(&short).is_err());
+        assert!(BinaryCodec::new(3).decode(&[0, 0, 0]).is_err());
+        assert!(BinaryCodec::new(3).decode(&[0, 0, 0, 5, 1]).is_err());
+    }
+
+    #[test]
+    fn ignores_trailing_bytes_after_payload() {
+        let decoded = BinaryCodec::new(3).decode(&[0, 0, 0, 1, 7, 8, 9]).unwrap().unwrap();
+        assert_eq!(decoded.payload, [7]);
+    }
+
+    #[test]
+    fn drops_non_audio_frames() {
+        let json_v2 = BinaryProtocol2 {
+            version: 2,
+            frame_type: 1,
+            reserved: 0,
+            timestamp: 0,
+            payload: b"{}".to_vec(),
+        }
+        .encode();
+        assert!(BinaryCodec::new(2).decode(&json_v2).unwrap().is_none());
+        assert!(BinaryCodec::new(3).decode(&[1, 0, 0, 2, b'{', b'}']).unwrap().is_none());
+    }
+}
@@ -0,0 +1,206 @@
+//! transport - 与小智服务器通信的传输层
+//!
+//! NetLink 负责重连，具体的连接建立和收发由 Transport 实现：
+//! - websocket: 控制消息和音频都走同一条 WebSocket
+//! - mqtt: 控制消息走 MQTT，音频走 AES-128-CTR 加密的 UDP
+
+mod binary_protocol;
+mod mqtt;
+mod udp_audio;
+mod websocket;
+
+pub use mqtt::MqttTransport;
+pub use websocket::WebSocketTransport;
+
+use crate::config::{AudioStreamFormat, Config};
+use crate::mcp_gateway::McpServer;
+use crate::net_link::{NetCommand, NetEvent, SessionParams};
+use crate::protocol::{McpMessage, ServerHello, ServerMessage};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// 发送 hello 后等待服务器 hello 的超时时间
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[async_trait]
+pub trait Transport: Send {
+    fn name(&self) -> &str;
+
+    /// 最近一次 run 中握手完成的时间，握手未完成时为 None
+    fn connected_at(&self) -> Option<Instant>;
+
+    /// 建立连接并收发消息
+    /// 连接断开时返回 Err，由 NetLink 负责重连；rx_cmd 关闭时返回 Ok
+    async fn run(
+        &mut self,
+        tx: &mpsc::Sender<NetEvent>,
+        rx_cmd: &mut mpsc::Receiver<NetCommand>,
+    ) -> anyhow::Result<()>;
+}
+
+// 音频参数结构体
+#[derive(Serialize)]
+struct AudioParams {
+    format: String,
+    sample_rate: u32,
+    channels: u8,
+    frame_duration: u32,
+}
+
+// Features 声明结构体，用于告知服务端设备支持的能力
+#[derive(Serialize)]
+struct Features {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mcp: Option<bool>,
+}
+
+// Hello Message，用于初始化连接
+#[derive(Serialize)]
+struct HelloMessage<'a> {
+    #[serde(rename = "type")]
+    msg_type: &'a str,
+    version: u8,
+    transport: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    features: Option<Features>,
+    audio_params: AudioParams,
+}
+
+/// 构造客户端 hello 消息，transport 为 "websocket" 或 "udp"，version 为二进制协议版本
+fn build_hello(config: &Config, version: u8, transport: &str) -> anyhow::Result<String> {
+    // 根据配置动态决定是否在 hello 中声明 MCP 能力
+    let features = if config.mcp.enabled {
+        Some(Features { mcp: Some(true) })
+    } else {
+        None
+    };
+    let hello_msg = HelloMessage {
+        msg_type: "hello",
+        version,
+        transport,
+        features,
+        audio_params: AudioParams {
+            format: config.hello_format.to_string(),
+            sample_rate: config.hello_sample_rate,
+            channels: config.hello_channels,
+            frame_duration: config.hello_frame_duration,
+        },
+    };
+    Ok(serde_json::to_string(&hello_msg)?)
+}
+
+/// 校验服务器 hello，返回协商后的会话参数
+/// 缺少 transport / session_id / audio_params，或音频参数与本地解码器不兼容时返回错误
+fn negotiate_session(
+    config: &Config,
+    hello: &ServerHello,
+    expected_transport: &str,
+) -> anyhow::Result<SessionParams> {
+    let transport = hello
+        .transport
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Server hello missing transport"))?;
+    if transport != expected_transport {
+        anyhow::bail!(
+            "Server hello transport mismatch: expected {}, got {}",
+            expected_transport,
+            transport
+        );
+    }
+
+    let session_id = hello
+        .session_id
+        .as_deref()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Server hello missing session_id"))?;
+
+    let audio_params = hello
+        .audio_params
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("Server hello missing audio_params"))?;
+    // 未声明 format 时按本地配置的 stream_format 处理
+    if let Some(format) = &audio_params.format
+        && !format.eq_ignore_ascii_case(config.stream_format.as_str())
+    {
+        anyhow::bail!(
+            "Server audio format {} incompatible with stream_format {}",
+            format,
+            config.stream_format
+        );
+    }
+    // 采样率 / 声道数缺省时沿用本地配置，只校验服务器实际下发的值
+    if let Some(sample_rate) = audio_params.sample_rate {
+        let rate_supported = match config.stream_format {
+            // Opus 解码器只支持这几种采样率
+            AudioStreamFormat::Opus => {
+                matches!(sample_rate, 8000 | 12000 | 16000 | 24000 | 48000)
+            }
+            _ => (8000..=48000).contains(&sample_rate),
+        };
+        if !rate_supported {
+            anyhow::bail!("Unsupported server audio sample rate: {}Hz", sample_rate);
+        }
+    }
+    if let Some(channels) = audio_params.channels
+        && !(1..=2).contains(&channels)
+    {
+        anyhow::bail!("Unsupported server audio channels: {}", channels);
+    }
+
+    log::info!(
+        "Server hello accepted: transport={}, version={:?}, session_id={}, audio={}/{:?}Hz/{:?}ch/{:?}ms",
+        transport,
+        hello.version,
+        session_id,
+        audio_params.format.as_deref().unwrap_or(config.stream_format.as_str()),
+        audio_params.sample_rate,
+        audio_params.channels,
+        audio_params.frame_duration
+    );
+
+    Ok(SessionParams {
+        session_id: session_id.to_string(),
+        transport: transport.to_string(),
+        audio_params,
+    })
+}
+
+/// 处理服务器下发的文本消息
+/// MCP 请求交给 MCP Gateway 处理，返回需要回复给服务器的消息；其余消息转发给 Controller
+async fn dispatch_text(
+    text: &str,
+    mcp_server: &McpServer,
+    tx: &mpsc::Sender<NetEvent>,
+) -> anyhow::Result<Option<String>> {
+    // 服务端下发的 MCP 消息格式: {"type":"mcp","payload":{JSON-RPC},"session_id":"..."}
+    if let Ok(ServerMessage::Mcp(McpMessage { session_id, payload: Some(payload) })) =
+        serde_json::from_str::<ServerMessage>(text)
+    {
+        let payload_str = payload.to_string();
+        log::info!("MCP Request: {}", payload_str);
+        match mcp_server.handle_message(&payload_str).await {
+            // 通知消息，无需回复
+            Some(mcp_response) if mcp_response.is_empty() => return Ok(None),
+            Some(mcp_response) => {
+                // 将 MCP 响应包装回信封格式发送
+                let response_envelope = json!({
+                    "type": "mcp",
+                    "session_id": session_id.as_deref().unwrap_or(""),
+                    "payload": serde_json::from_str::<Value>(&mcp_response).unwrap_or(Value::Null)
+                });
+                let response_text = serde_json::to_string(&response_envelope)?;
+                log::info!("MCP Response: {}", response_text);
+                return Ok(Some(response_text));
+            }
+            None => {}
+        }
+    }
+
+    // 正常信令通道处理
+    log::info!("Received Text: {}", text);
+    tx.send(NetEvent::Text(text.to_string())).await?;
+    Ok(None)
+}
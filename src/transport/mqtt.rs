@@ -0,0 +1,323 @@
+use super::udp_audio::UdpAudioSession;
+use super::{build_hello, dispatch_text, negotiate_session, Transport, HELLO_TIMEOUT};
+use crate::config::Config;
+use crate::mcp_gateway::McpServer;
+use crate::net_link::{AudioFrame, NetCommand, NetEvent, SessionParams};
+use crate::outbound;
+use crate::protocol::ServerMessage;
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Proxy, ProxyAuth, ProxyType, QoS, TlsConfiguration};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const DEFAULT_MQTT_PORT: u16 = 8883;
+// 1883 是明文 MQTT 端口，其余端口一律使用 TLS
+const PLAIN_MQTT_PORT: u16 = 1883;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const KEEP_ALIVE: Duration = Duration::from_secs(90);
+// UDP 音频通道统计的打印周期
+const UDP_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+// MQTT 事件循环转发给主循环的事件
+enum MqttIncoming {
+    Connected,
+    Message(String),
+}
+
+// rumqttc 的事件循环需要持续 poll，放在独立任务中，连接结束时随之终止
+struct EventLoopTask(JoinHandle<()>);
+
+impl Drop for EventLoopTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+// 控制消息走 MQTT，音频走服务器 hello 中下发的加密 UDP 通道
+pub struct MqttTransport {
+    config: Config,
+    mcp_server: Arc<McpServer>,
+    connected_at: Option<Instant>,
+}
+
+impl MqttTransport {
+    pub fn new(config: Config, mcp_server: Arc<McpServer>) -> Self {
+        Self {
+            config,
+            mcp_server,
+            connected_at: None,
+        }
+    }
+
+    fn mqtt_options(&self) -> anyhow::Result<MqttOptions> {
+        let mqtt = &self.config.mqtt;
+        let (host, port) = parse_endpoint(&mqtt.endpoint)?;
+        let client_id = if mqtt.client_id.is_empty() {
+            self.config.client_id.clone()
+        } else {
+            mqtt.client_id.clone()
+        };
+
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_max_packet_size(64 * 1024, 64 * 1024);
+        if !mqtt.username.is_empty() {
+            options.set_credentials(mqtt.username.clone(), mqtt.password.clone());
+        }
+        if port != PLAIN_MQTT_PORT {
+            options.set_transport(rumqttc::Transport::tls_with_config(
+                TlsConfiguration::Rustls(outbound::tls_client_config(&self.config.outbound)?),
+            ));
+        }
+        // rumqttc 只支持 HTTP CONNECT 代理
+        if let Some(proxy) = outbound::proxy_url(&self.config.outbound)? {
+            if proxy.scheme() != "http" {
+                anyhow::bail!("{} proxy is not supported for MQTT", proxy.scheme());
+            }
+            let auth = if proxy.username().is_empty() {
+                ProxyAuth::None
+            } else {
+                ProxyAuth::Basic {
+                    username: proxy.username().to_string(),
+                    password: proxy.password().unwrap_or_default().to_string(),
+                }
+            };
+            options.set_proxy(Proxy {
+                ty: ProxyType::Http,
+                auth,
+                addr: proxy.host_str().unwrap_or_default().to_string(),
+                port: proxy.port_or_known_default().unwrap_or(80),
+            });
+        }
+        Ok(options)
+    }
+
+    async fn publish(&self, client: &AsyncClient, text: String) -> anyhow::Result<()> {
+        client
+            .publish(&self.config.mqtt.publish_topic, QoS::AtMostOnce, false, text)
+            .await?;
+        Ok(())
+    }
+
+    // 发送 hello 并等待服务器 hello，校验通过后按其中的参数建立 UDP 音频通道
+    async fn handshake(
+        &self,
+        client: &AsyncClient,
+        mqtt_rx: &mut mpsc::Receiver<anyhow::Result<MqttIncoming>>,
+    ) -> anyhow::Result<(SessionParams, UdpAudioSession)> {
+        let hello_json = build_hello(&self.config, 3, "udp")?;
+        log::info!("Sending Hello: {}", hello_json);
+        self.publish(client, hello_json).await?;
+
+        tokio::time::timeout(HELLO_TIMEOUT, async {
+            loop {
+                match mqtt_rx.recv().await {
+                    Some(Ok(MqttIncoming::Message(text))) => {
+                        if let Ok(ServerMessage::Hello(hello)) = serde_json::from_str(&text) {
+                            let session = negotiate_session(&self.config, &hello, "udp")?;
+                            let params = hello
+                                .udp
+                                .as_ref()
+                                .ok_or_else(|| anyhow::anyhow!("Server hello missing udp parameters"))?;
+                            let udp = UdpAudioSession::connect(params).await?;
+                            return Ok((session, udp));
+                        }
+                        log::warn!("Ignoring message before server hello: {}", text);
+                    }
+                    Some(Ok(MqttIncoming::Connected)) => {}
+                    Some(Err(e)) => return Err(e),
+                    None => anyhow::bail!("MQTT event loop stopped"),
+                }
+            }
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out waiting for server hello"))?
+    }
+}
+
+#[async_trait]
+impl Transport for MqttTransport {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    fn connected_at(&self) -> Option<Instant> {
+        self.connected_at
+    }
+
+    async fn run(
+        &mut self,
+        tx: &mpsc::Sender<NetEvent>,
+        rx_cmd: &mut mpsc::Receiver<NetCommand>,
+    ) -> anyhow::Result<()> {
+        self.connected_at = None;
+        if !self.config.mqtt.is_configured() {
+            anyhow::bail!("MQTT endpoint is not configured");
+        }
+
+        let options = self.mqtt_options()?;
+        log::info!("Connecting to MQTT broker {}...", self.config.mqtt.endpoint);
+        let (client, mut eventloop) = AsyncClient::new(options, 16);
+
+        let (mqtt_tx, mut mqtt_rx) = mpsc::channel::<anyhow::Result<MqttIncoming>>(32);
+        let _event_loop_task = EventLoopTask(tokio::spawn(async move {
+            loop {
+                let incoming = match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => Ok(MqttIncoming::Connected),
+                    Ok(Event::Incoming(Packet::Publish(publish))) => Ok(MqttIncoming::Message(
+                        String::from_utf8_lossy(&publish.payload).into_owned(),
+                    )),
+                    Ok(_) => continue,
+                    Err(e) => Err(e.into()),
+                };
+                let failed = incoming.is_err();
+                if mqtt_tx.send(incoming).await.is_err() || failed {
+                    break;
+                }
+            }
+        }));
+
+        match tokio::time::timeout(CONNECT_TIMEOUT, mqtt_rx.recv()).await {
+            Ok(Some(Ok(MqttIncoming::Connected))) => {}
+            Ok(Some(Err(e))) => return Err(e),
+            Ok(_) => anyhow::bail!("MQTT connection closed before CONNACK"),
+            Err(_) => anyhow::bail!("MQTT connect timed out"),
+        }
+        log::info!("MQTT connected!");
+
+        let subscribe_topic = &self.config.mqtt.subscribe_topic;
+        if !subscribe_topic.is_empty() && subscribe_topic != "null" {
+            client.subscribe(subscribe_topic, QoS::AtMostOnce).await?;
+        }
+
+        let (session, session_udp) = self.handshake(&client, &mut mqtt_rx).await?;
+        let mut udp = Some(session_udp);
+        self.connected_at = Some(Instant::now());
+        tx.send(NetEvent::Connected(session)).await?;
+        let mut stats_timer = tokio::time::interval(UDP_STATS_INTERVAL);
+
+        loop {
+            tokio::select! {
+                incoming = mqtt_rx.recv() => {
+                    match incoming {
+                        Some(Ok(MqttIncoming::Message(text))) => {
+                            let goodbye = matches!(
+                                serde_json::from_str::<ServerMessage>(&text),
+                                Ok(ServerMessage::Goodbye(_))
+                            );
+
+                            if let Some(reply) = dispatch_text(&text, &self.mcp_server, tx).await? {
+                                self.publish(&client, reply).await?;
+                            }
+
+                            // 服务器结束会话后关闭 UDP 通道，重新 hello 开启新会话
+                            if goodbye {
+                                log::info!("Session closed by server, reopening audio channel");
+                                // 先关闭旧通道（打印统计），再握手建立新通道
+                                drop(udp.take());
+                                let (session, session_udp) =
+                                    self.handshake(&client, &mut mqtt_rx).await?;
+                                udp = Some(session_udp);
+                                tx.send(NetEvent::Connected(session)).await?;
+                            }
+                        }
+                        Some(Ok(MqttIncoming::Connected)) => {}
+                        Some(Err(e)) => return Err(e),
+                        None => return Err(anyhow::anyhow!("MQTT event loop stopped")),
+                    }
+                }
+                data = recv_udp(&mut udp) => {
+                    // UDP 通道出错时断开整个连接，由 NetLink 按退避策略重连并重新握手
+                    let frame = data.map_err(|e| anyhow::anyhow!("UDP audio channel error: {}", e))?;
+                    tx.send(NetEvent::Binary(frame)).await?;
+                }
+                _ = stats_timer.tick() => {
+                    if let Some(session) = udp.as_ref() {
+                        log::info!("UDP audio stats: {}", session.stats());
+                    }
+                }
+                cmd = rx_cmd.recv() => {
+                    match cmd {
+                        Some(NetCommand::SendText(text)) => self.publish(&client, text).await?,
+                        Some(NetCommand::SendBinary(frame)) => match udp.as_mut() {
+                            Some(session) => session.send(&frame).await?,
+                            None => log::debug!("UDP audio channel not open, audio packet dropped"),
+                        },
+                        // 命令通道关闭，程序正在退出
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        let _ = client.disconnect().await;
+        Ok(())
+    }
+}
+
+// UDP 通道未建立时永远挂起，使 select! 中的该分支不会被选中
+async fn recv_udp(session: &mut Option<UdpAudioSession>) -> anyhow::Result<AudioFrame> {
+    match session {
+        Some(session) => session.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// 解析 "host:port"，省略端口时使用 8883；IPv6 地址须写成 "[::1]:8883" 或 "[::1]"
+fn parse_endpoint(endpoint: &str) -> anyhow::Result<(String, u16)> {
+    let invalid = || anyhow::anyhow!("Invalid MQTT endpoint: {}", endpoint);
+    let (host, port) = if let Some(rest) = endpoint.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+        match rest {
+            "" => (host, None),
+            _ => (host, Some(rest.strip_prefix(':').ok_or_else(invalid)?)),
+        }
+    } else {
+        match endpoint.split_once(':') {
+            Some((_, port)) if port.contains(':') => {
+                anyhow::bail!("Invalid MQTT endpoint: {} (IPv6 address must be in brackets)", endpoint)
+            }
+            Some((host, port)) => (host, Some(port)),
+            None => (endpoint, None),
+        }
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| invalid())?,
+        None => DEFAULT_MQTT_PORT,
+    };
+    Ok((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(endpoint: &str) -> (String, u16) {
+        parse_endpoint(endpoint).unwrap()
+    }
+
+    #[test]
+    fn parses_host_and_port() {
+        assert_eq!(parse("mqtt.example.com:1883"), ("mqtt.example.com".to_string(), 1883));
+        assert_eq!(parse("mqtt.example.com"), ("mqtt.example.com".to_string(), DEFAULT_MQTT_PORT));
+        assert_eq!(parse("10.0.0.1:8883"), ("10.0.0.1".to_string(), 8883));
+    }
+
+    #[test]
+    fn parses_bracketed_ipv6() {
+        assert_eq!(parse("[::1]:1883"), ("::1".to_string(), 1883));
+        assert_eq!(parse("[fe80::1]"), ("fe80::1".to_string(), DEFAULT_MQTT_PORT));
+    }
+
+    #[test]
+    fn rejects_invalid_endpoints() {
+        for endpoint in ["::1", "fe80::1:1883", "[::1", "[::1]1883", "[]:1883", ":1883", "host:", "host:x"] {
+            assert!(parse_endpoint(endpoint).is_err(), "{endpoint}");
+        }
+    }
+}
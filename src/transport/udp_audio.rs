@@ -0,0 +1,273 @@
+use crate::net_link::AudioFrame;
+use crate::protocol::UdpParams;
+use aes::Aes128;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use tokio::net::UdpSocket;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+// UDP 音频包格式：16 字节 nonce + AES-128-CTR 密文，nonce 同时作为 CTR 的初始计数器
+// nonce: [type:1][flags:1][payload_len:2][ssrc:4][timestamp:4][sequence:4]，多字节字段为大端
+const PACKET_TYPE_AUDIO: u8 = 0x01;
+const NONCE_SIZE: usize = 16;
+const MAX_PACKET_SIZE: usize = 1500;
+
+/// UDP 音频通道的收发计数
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UdpAudioStats {
+    pub sent: u64,
+    pub received: u64,
+    /// 序号跳变推算出的丢包数
+    pub lost: u64,
+    /// 序号小于已收到的最大序号（迟到的乱序包），已丢弃
+    pub reordered: u64,
+    /// 与上一个包序号相同的重复包，已丢弃
+    pub duplicated: u64,
+    /// 类型或长度不合法的包，已丢弃
+    pub invalid: u64,
+}
+
+impl std::fmt::Display for UdpAudioStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sent={}, received={}, lost={}, reordered={}, duplicated={}, invalid={}",
+            self.sent, self.received, self.lost, self.reordered, self.duplicated, self.invalid
+        )
+    }
+}
+
+/// MQTT 传输下的加密 UDP 音频通道
+pub struct UdpAudioSession {
+    socket: UdpSocket,
+    key: [u8; 16],
+    nonce: [u8; 16],
+    local_sequence: u32,
+    // 已交给播放的最大远端序号，收到第一个包之前为 None
+    remote_sequence: Option<u32>,
+    recv_buf: Vec<u8>,
+    stats: UdpAudioStats,
+}
+
+impl UdpAudioSession {
+    /// 根据服务器 hello 下发的参数建立 UDP 通道
+    pub async fn connect(params: &UdpParams) -> anyhow::Result<Self> {
+        let key = decode_block(&params.key, "key")?;
+        let nonce = decode_block(&params.nonce, "nonce")?;
+
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect((params.server.as_str(), params.port)).await?;
+        log::info!("UDP audio channel connected to {}:{}", params.server, params.port);
+
+        Ok(Self {
+            socket,
+            key,
+            nonce,
+            local_sequence: 0,
+            remote_sequence: None,
+            recv_buf: vec![0u8; MAX_PACKET_SIZE],
+            stats: UdpAudioStats::default(),
+        })
+    }
+
+    /// 加密并发送一个音频包
+    pub async fn send(&mut self, frame: &AudioFrame) -> anyhow::Result<()> {
+        self.local_sequence = self.local_sequence.wrapping_add(1);
+        let payload = &frame.payload;
+
+        let mut nonce = self.nonce;
+        nonce[2..4].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+        nonce[8..12].copy_from_slice(&frame.timestamp.to_be_bytes());
+        nonce[12..16].copy_from_slice(&self.local_sequence.to_be_bytes());
+
+        let mut packet = Vec::with_capacity(NONCE_SIZE + payload.len());
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(payload);
+        Aes128Ctr::new(&self.key.into(), &nonce.into()).apply_keystream(&mut packet[NONCE_SIZE..]);
+
+        self.socket.send(&packet).await?;
+        self.stats.sent += 1;
+        Ok(())
+    }
+
+    /// 接收并解密一个音频包
+    /// 格式不对、重复以及迟到的乱序包直接丢弃，解码器只能按顺序消费音频帧
+    pub async fn recv(&mut self) -> anyhow::Result<AudioFrame> {
+        loop {
+            let len = self.socket.recv(&mut self.recv_buf).await?;
+            if len < NONCE_SIZE || self.recv_buf[0] != PACKET_TYPE_AUDIO {
+                self.stats.invalid += 1;
+                log::warn!("Invalid UDP audio packet ({} bytes), dropped", len);
+                continue;
+            }
+
+            let mut nonce = [0u8; NONCE_SIZE];
+            nonce.copy_from_slice(&self.recv_buf[..NONCE_SIZE]);
+            let timestamp = u32::from_be_bytes([nonce[8], nonce[9], nonce[10], nonce[11]]);
+            let sequence = u32::from_be_bytes([nonce[12], nonce[13], nonce[14], nonce[15]]);
+            if !self.track_sequence(sequence) {
+                continue;
+            }
+
+            self.stats.received += 1;
+            let mut payload = self.recv_buf[NONCE_SIZE..len].to_vec();
+            Aes128Ctr::new(&self.key.into(), &nonce.into()).apply_keystream(&mut payload);
+            return Ok(AudioFrame { timestamp, payload });
+        }
+    }
+
+    pub fn stats(&self) -> UdpAudioStats {
+        self.stats
+    }
+
+    // 更新远端序号，返回该包是否应交给播放
+    fn track_sequence(&mut self, sequence: u32) -> bool {
+        let Some(last) = self.remote_sequence else {
+            self.remote_sequence = Some(sequence);
+            return true;
+        };
+
+        // 按回绕差值比较，序号溢出后仍能正确判断先后
+        let delta = sequence.wrapping_sub(last) as i32;
+        if delta == 0 {
+            self.stats.duplicated += 1;
+            log::debug!("Duplicate UDP audio packet seq={}, dropped", sequence);
+            return false;
+        }
+        if delta < 0 {
+            self.stats.reordered += 1;
+            log::debug!(
+                "Out-of-order UDP audio packet seq={} (expected > {}), dropped",
+                sequence,
+                last
+            );
+            return false;
+        }
+        if delta > 1 {
+            let lost = (delta - 1) as u64;
+            self.stats.lost += lost;
+            log::warn!(
+                "UDP audio packets lost: {} (seq {} -> {})",
+                lost,
+                last,
+                sequence
+            );
+        }
+        self.remote_sequence = Some(sequence);
+        true
+    }
+}
+
+impl Drop for UdpAudioSession {
+    fn drop(&mut self) {
+        log::info!("UDP audio channel closed: {}", self.stats);
+    }
+}
+
+// 将十六进制字符串解析为 16 字节的 AES key / nonce
+fn decode_block(hex_str: &str, what: &str) -> anyhow::Result<[u8; 16]> {
+    let bytes = hex::decode(hex_str)
+        .map_err(|e| anyhow::anyhow!("Invalid UDP {} in server hello: {}", what, e))?;
+    bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| anyhow::anyhow!("UDP {} must be 16 bytes, got {}", what, b.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f";
+    const NONCE: &str = "01000000aabbccdd0000000000000000";
+
+    async fn session_with_server() -> (UdpAudioSession, UdpSocket) {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let params = UdpParams {
+            server: "127.0.0.1".to_string(),
+            port: server.local_addr().unwrap().port(),
+            key: KEY.to_string(),
+            nonce: NONCE.to_string(),
+        };
+        (UdpAudioSession::connect(&params).await.unwrap(), server)
+    }
+
+    #[tokio::test]
+    async fn encrypts_with_header_and_round_trips() {
+        let (mut session, server) = session_with_server().await;
+        let frame = AudioFrame {
+            timestamp: 0x0102_0304,
+            payload: b"opus frame".to_vec(),
+        };
+        session.send(&frame).await.unwrap();
+
+        let mut packet = [0u8; MAX_PACKET_SIZE];
+        let (len, peer) = server.recv_from(&mut packet).await.unwrap();
+        let packet = &packet[..len];
+        assert_eq!(len, NONCE_SIZE + frame.payload.len());
+        assert_eq!(packet[0], PACKET_TYPE_AUDIO);
+        assert_eq!(&packet[2..4], &(frame.payload.len() as u16).to_be_bytes());
+        assert_eq!(&packet[4..8], &[0xaa, 0xbb, 0xcc, 0xdd]);
+        assert_eq!(&packet[8..12], &frame.timestamp.to_be_bytes());
+        assert_eq!(&packet[12..16], &1u32.to_be_bytes());
+        assert_ne!(&packet[NONCE_SIZE..], frame.payload.as_slice());
+
+        // The server encrypts the same way, so echoing the packet back must decrypt
+        server.send_to(packet, peer).await.unwrap();
+        let received = session.recv().await.unwrap();
+        assert_eq!(received.payload, frame.payload);
+        assert_eq!(received.timestamp, frame.timestamp);
+    }
+
+    #[tokio::test]
+    async fn drops_invalid_packets() {
+        let (mut session, server) = session_with_server().await;
+        session
+            .send(&AudioFrame {
+                timestamp: 0,
+                payload: vec![1, 2, 3],
+            })
+            .await
+            .unwrap();
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+
+        server.send_to(&[0x02; 20], peer).await.unwrap();
+        server.send_to(&[PACKET_TYPE_AUDIO; 8], peer).await.unwrap();
+        server.send_to(&buf[..len], peer).await.unwrap();
+        assert_eq!(session.recv().await.unwrap().payload, vec![1, 2, 3]);
+        assert_eq!(session.stats().invalid, 2);
+    }
+
+    #[tokio::test]
+    async fn tracks_loss_duplicates_and_reordering() {
+        let (mut session, _server) = session_with_server().await;
+        assert!(session.track_sequence(10));
+        assert!(session.track_sequence(11));
+        assert!(!session.track_sequence(11));
+        assert!(session.track_sequence(15));
+        assert!(!session.track_sequence(13));
+
+        let stats = session.stats();
+        assert_eq!((stats.lost, stats.duplicated, stats.reordered), (3, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn tracks_sequence_across_wraparound() {
+        let (mut session, _server) = session_with_server().await;
+        assert!(session.track_sequence(u32::MAX - 1));
+        assert!(session.track_sequence(u32::MAX));
+        assert!(session.track_sequence(0));
+        assert!(session.track_sequence(2));
+        assert!(!session.track_sequence(u32::MAX));
+
+        let stats = session.stats();
+        assert_eq!((stats.lost, stats.reordered), (1, 1));
+    }
+
+    #[test]
+    fn rejects_malformed_key() {
+        assert!(decode_block("0011", "key").is_err());
+        assert!(decode_block("zz0102030405060708090a0b0c0d0e0f", "key").is_err());
+        assert_eq!(decode_block(KEY, "key").unwrap()[15], 0x0f);
+    }
+}
@@ -0,0 +1,215 @@
+use super::binary_protocol::BinaryCodec;
+use super::{build_hello, dispatch_text, negotiate_session, Transport, HELLO_TIMEOUT};
+use crate::config::Config;
+use crate::mcp_gateway::McpServer;
+use crate::net_link::{NetCommand, NetEvent};
+use crate::outbound;
+use crate::protocol::ServerMessage;
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use mac_address::get_mac_address;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_tungstenite::{client_async_tls_with_config, tungstenite::protocol::Message, Connector};
+use url::Url;
+use uuid::Uuid;
+
+// 心跳与超时检查的周期
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+
+// 控制消息和音频都走同一条 WebSocket 连接
+pub struct WebSocketTransport {
+    config: Config,
+    mcp_server: Arc<McpServer>,
+    connected_at: Option<Instant>,
+}
+
+impl WebSocketTransport {
+    pub fn new(config: Config, mcp_server: Arc<McpServer>) -> Self {
+        Self {
+            config,
+            mcp_server,
+            connected_at: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for WebSocketTransport {
+    fn name(&self) -> &str {
+        "websocket"
+    }
+
+    fn connected_at(&self) -> Option<Instant> {
+        self.connected_at
+    }
+
+    // 进入连接和主循环，处理WebSocket消息和发送命令
+    async fn run(
+        &mut self,
+        tx: &mpsc::Sender<NetEvent>,
+        rx_cmd: &mut mpsc::Receiver<NetCommand>,
+    ) -> anyhow::Result<()> {
+        self.connected_at = None;
+        // 如果设备ID是unknown-device，则尝试获取MAC地址作为设备ID
+        let device_id = if self.config.device_id == "unknown-device" {
+            match get_mac_address() {
+                Ok(Some(mac)) => mac.to_string().to_lowercase(), // Ensure lowercase to match typical Linux behavior 注意大小写一致，以匹配典型的Linux行为
+                _ => Uuid::new_v4().to_string(), // 如果无法获取MAC地址，则生成新的UUID
+            }
+        } else {
+            self.config.device_id.clone() // 使用配置中的设备ID
+        };
+
+        // 根据配置构建WebSocket请求
+        let url = Url::parse(self.config.ws_url.as_ref())?;
+        let host = url.host_str().unwrap_or("api.tenclass.net");
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| anyhow::anyhow!("Invalid WebSocket URL: {}", self.config.ws_url))?;
+
+        let request = tokio_tungstenite::tungstenite::http::Request::builder()
+            .method("GET")
+            .uri(self.config.ws_url.as_ref())
+            .header("Host", host)
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header(
+                "Sec-WebSocket-Key",
+                tokio_tungstenite::tungstenite::handshake::client::generate_key(),
+            )
+            .header("Authorization", format!("Bearer {}", self.config.ws_token))
+            .header("Device-Id", &device_id)
+            .header("Client-Id", &self.config.client_id)
+            .header("Protocol-Version", self.config.ws_protocol_version.to_string())
+            .body(())?;
+
+        log::info!("Connecting to {}...", self.config.ws_url);
+        log::debug!("Headers: {:?}", request.headers()); // Debug headers
+        // TCP 连接按 [outbound] 配置走代理，wss 使用自定义 CA / 客户端证书
+        let stream = outbound::connect_tcp(&self.config.outbound, host, port).await?;
+        let connector = Connector::Rustls(outbound::tls_client_config(&self.config.outbound)?);
+        let (ws_stream, _) =
+            client_async_tls_with_config(request, stream, None, Some(connector)).await?;
+        log::info!("Connected!");
+
+        let (mut write, mut read) = ws_stream.split();
+        let codec = BinaryCodec::new(self.config.ws_protocol_version);
+
+        // 发送Hello消息进行初始化链接
+        let hello_json = build_hello(&self.config, self.config.ws_protocol_version, "websocket")?;
+        log::info!("Sending Hello: {}", hello_json);
+        write.send(Message::Text(hello_json.into())).await?;
+
+        // 服务器 hello 校验通过后才认为链路可用
+        let session = tokio::time::timeout(HELLO_TIMEOUT, async {
+            loop {
+                match read.next().await {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Ok(ServerMessage::Hello(hello)) = serde_json::from_str(&text) {
+                            return negotiate_session(&self.config, &hello, "websocket");
+                        }
+                        log::warn!("Ignoring message before server hello: {}", text);
+                    }
+                    Some(Ok(Message::Close(frame))) => {
+                        log::info!("Server closed connection: {:?}", frame);
+                        anyhow::bail!("Connection closed during hello");
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => anyhow::bail!("Connection closed during hello"),
+                }
+            }
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out waiting for server hello"))??;
+
+        self.connected_at = Some(Instant::now());
+        tx.send(NetEvent::Connected(session)).await?;
+
+        // 心跳：半开连接（如 Wi-Fi 掉线）不会立刻报错，靠 Pong 超时和读空闲超时发现
+        let ping_interval = Duration::from_secs(self.config.ws_ping_interval_secs as u64);
+        let pong_timeout = Duration::from_secs(self.config.ws_pong_timeout_secs as u64);
+        let read_timeout = Duration::from_secs(self.config.ws_read_timeout_secs as u64);
+        let mut watchdog = tokio::time::interval(WATCHDOG_INTERVAL);
+        let mut last_read = Instant::now();
+        let mut last_ping = Instant::now();
+        // 已发出但尚未收到 Pong 的 Ping：(序号, 发送时间)
+        let mut pending_ping: Option<(u64, Instant)> = None;
+        let mut ping_seq: u64 = 0;
+
+        // 主循环，处理读取和写入
+        loop {
+            tokio::select! {
+                msg = read.next() => {
+                    last_read = Instant::now();
+                    match msg {
+                        Some(Ok(msg)) => {
+                            match msg {
+                                Message::Text(text) => {
+                                    if let Some(reply) = dispatch_text(&text, &self.mcp_server, tx).await? {
+                                        write.send(Message::Text(reply.into())).await?;
+                                    }
+                                }
+                                Message::Binary(data) => match codec.decode(&data) {
+                                    Ok(Some(frame)) => tx.send(NetEvent::Binary(frame)).await?,
+                                    Ok(None) => {}
+                                    Err(e) => log::warn!("Invalid binary frame: {}", e),
+                                },
+                                Message::Pong(payload) => {
+                                    if let Some((seq, sent_at)) = pending_ping
+                                        && payload.as_ref() == seq.to_be_bytes()
+                                    {
+                                        pending_ping = None;
+                                        tx.send(NetEvent::Rtt(sent_at.elapsed())).await?;
+                                    }
+                                }
+                                Message::Close(frame) => {
+                                    log::info!("Server closed connection: {:?}", frame);
+                                    return Err(anyhow::anyhow!("Connection closed"));
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Err(anyhow::anyhow!("Connection closed")),
+                    }
+                }
+                _ = watchdog.tick() => {
+                    if let Some((_, sent_at)) = pending_ping
+                        && sent_at.elapsed() >= pong_timeout
+                    {
+                        anyhow::bail!("No pong within {}s", pong_timeout.as_secs());
+                    }
+                    if !read_timeout.is_zero() && last_read.elapsed() >= read_timeout {
+                        anyhow::bail!("No data from server for {}s", read_timeout.as_secs());
+                    }
+                    if !ping_interval.is_zero()
+                        && pending_ping.is_none()
+                        && last_ping.elapsed() >= ping_interval
+                    {
+                        ping_seq += 1;
+                        last_ping = Instant::now();
+                        pending_ping = Some((ping_seq, last_ping));
+                        write.send(Message::Ping(ping_seq.to_be_bytes().to_vec().into())).await?;
+                    }
+                }
+                cmd = rx_cmd.recv() => {
+                    match cmd {
+                        Some(NetCommand::SendText(text)) => {
+                            write.send(Message::Text(text.into())).await?;
+                        }
+                        Some(NetCommand::SendBinary(frame)) => {
+                            write.send(Message::Binary(codec.encode(frame)?.into())).await?;
+                        }
+                        // 命令通道关闭，程序正在退出
+                        None => break,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}